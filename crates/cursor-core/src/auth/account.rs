@@ -0,0 +1,71 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{model_registry, services::chat::reset_all_chats};
+
+use super::{status::AuthStatus, store};
+
+#[wasm_bindgen(getter_with_clone, js_name = AccountSummary)]
+#[derive(Debug, Clone)]
+pub struct AccountSummary {
+    pub name: String,
+
+    /// Whether this account is used in the current workspace.
+    pub active: bool,
+
+    /// Whether this account is explicitly selected for the current workspace.
+    #[wasm_bindgen(js_name = selectedForWorkspace)]
+    pub selected_for_workspace: bool,
+
+    pub status: AuthStatus,
+}
+
+#[wasm_bindgen(js_name = listAccounts)]
+pub async fn list_accounts() -> Vec<AccountSummary> {
+    let active_account = store::load_active_account().await.map(|(name, _)| name);
+    let workspace_account = store::workspace_account_name();
+    store::load_accounts()
+        .await
+        .into_iter()
+        .map(|(name, token)| AccountSummary {
+            active: active_account.as_ref() == Some(&name),
+            selected_for_workspace: workspace_account.as_ref() == Some(&name),
+            status: Some((name.clone(), token)).into(),
+            name,
+        })
+        .collect()
+}
+
+/// Makes the given account the active one in all the workspaces that don't
+/// select an account explicitly.
+#[wasm_bindgen(js_name = switchAccount)]
+pub async fn switch_account(account_name: &str) -> Result<(), JsValue> {
+    ensure_account_exists(account_name).await?;
+    store::set_active_account(account_name);
+    // The conversation belongs to the previous account.
+    reset_all_chats();
+    model_registry::clear_cache();
+    Ok(())
+}
+
+/// Selects the account to use in the current workspace, or clears the
+/// selection if `account_name` is not specified.
+#[wasm_bindgen(js_name = setWorkspaceAccount)]
+pub async fn set_workspace_account(account_name: Option<String>) -> Result<(), JsValue> {
+    if let Some(account_name) = &account_name {
+        ensure_account_exists(account_name).await?;
+    }
+    store::set_workspace_account(account_name.as_deref());
+    reset_all_chats();
+    model_registry::clear_cache();
+    Ok(())
+}
+
+async fn ensure_account_exists(account_name: &str) -> Result<(), JsError> {
+    if store::load_accounts().await.contains_key(account_name) {
+        Ok(())
+    } else {
+        Err(JsError::new(&format!(
+            "Account \"{account_name}\" does not exist"
+        )))
+    }
+}
@@ -0,0 +1,262 @@
+//! Sign in with the OAuth authorization code flow with PKCE, receiving the
+//! redirect on a temporary HTTP server bound to the loopback interface.
+
+use std::{collections::HashMap, future::IntoFuture};
+
+use chrono::{Duration, Utc};
+use futures::{
+    future::{select, Either},
+    StreamExt,
+};
+use gloo::timers::future::TimeoutFuture;
+use node_bridge::{
+    bindings::AbortSignal, futures::Defer, http_client::HttpMethod, http_server::HttpServer,
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    bindings::progress::Progress,
+    request::{auth_host, error_message, make_request, JsonSendable},
+};
+
+use super::{base64_encode, random_bytes, sha256, sign_in::SignInState, token::Token, CLIENT_ID};
+
+const LOOPBACK_HOST: &str = "127.0.0.1";
+const CALLBACK_PATH: &str = "/callback";
+const SCOPE: &str = "openid profile email offline_access";
+const AUDIENCE: &str = "https://cursor.com";
+/// How long to wait for the browser to redirect back.
+const REDIRECT_TIMEOUT_MS: u32 = 10 * 60 * 1000;
+
+const SUCCEEDED_PAGE: &str =
+    "<!DOCTYPE html><html><body><h3>You have signed in to CodeCursor.</h3>\
+    <p>You can close this window and return to VS Code.</p></body></html>";
+const FAILED_PAGE: &str = "<!DOCTYPE html><html><body><h3>Failed to sign in to CodeCursor.</h3>\
+    <p>Please return to VS Code and try again.</p></body></html>";
+
+#[derive(Debug, Clone, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+    expires_in: i64,
+}
+
+pub struct LoopbackSignIn {
+    server: HttpServer,
+    port: u16,
+    verifier: String,
+    state: String,
+}
+
+impl LoopbackSignIn {
+    /// Starts the loopback server on a random port.
+    ///
+    /// ## Errors
+    ///
+    /// Returns an error if the server cannot be bound or its port is
+    /// unknown, in which case the caller should fall back to another sign in
+    /// method.
+    pub async fn start() -> Result<Self, JsValue> {
+        let server = HttpServer::bind(LOOPBACK_HOST, 0).await?;
+        let port = server
+            .port()
+            .ok_or_else(|| JsError::new("The port of the loopback server is unknown"))?;
+        Ok(Self {
+            server,
+            port,
+            verifier: base64_encode(random_bytes()),
+            state: base64_encode(random_bytes()),
+        })
+    }
+
+    fn redirect_uri(&self) -> String {
+        format!("http://{LOOPBACK_HOST}:{}{CALLBACK_PATH}", self.port)
+    }
+
+    pub fn login_url(&self) -> String {
+        let challenge = base64_encode(sha256(&self.verifier));
+        let query = [
+            ("response_type", "code"),
+            ("client_id", CLIENT_ID),
+            ("redirect_uri", &self.redirect_uri()),
+            ("scope", SCOPE),
+            ("audience", AUDIENCE),
+            ("state", &self.state),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+        ]
+        .into_iter()
+        .map(|(key, value)| format!("{key}={}", js_sys::encode_uri_component(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+        format!("https://{}/authorize?{query}", auth_host())
+    }
+
+    /// Waits for the redirect and exchanges the authorization code for
+    /// a token.
+    pub async fn run(mut self, progress: &Progress, abort_signal: AbortSignal) -> SignInState {
+        let defer_abort = Defer::new();
+        let defer_abort_clone = defer_abort.clone();
+        abort_signal.add_event_listener(
+            "abort",
+            closure_once!(|| {
+                defer_abort_clone.resolve(JsValue::null());
+            })
+            .into_js_value(),
+        );
+
+        progress.report("Complete the sign in in your browser.");
+        let redirect = select(
+            TimeoutFuture::new(REDIRECT_TIMEOUT_MS),
+            Box::pin(self.wait_for_code()),
+        );
+        let code = match select(defer_abort.into_future(), redirect).await {
+            Either::Left(_) => return SignInState::Cancelled,
+            Either::Right((Either::Left(_), _)) => return SignInState::Expired,
+            Either::Right((Either::Right((Ok(code), _)), _)) => code,
+            Either::Right((Either::Right((Err(reason), _)), _)) => {
+                return SignInState::Failed(reason)
+            }
+        };
+
+        progress.report("Signing in...");
+        match self.exchange_code(&code).await {
+            Ok(token) => SignInState::Succeeded(token),
+            Err(reason) => SignInState::Failed(reason),
+        }
+    }
+
+    async fn wait_for_code(&mut self) -> Result<String, String> {
+        let expected_state = self.state.clone();
+        while let Some(request) = self.server.requests().next().await {
+            let url = request.url();
+            let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+            if path != CALLBACK_PATH {
+                // Browsers may request other resources like `/favicon.ico`.
+                request.respond(404, "text/plain", "Not Found");
+                continue;
+            }
+
+            match parse_redirect(parse_query(query), &expected_state) {
+                // Not a redirect of the flow we started.
+                None => request.respond(400, "text/plain", "Bad Request"),
+                Some(Ok(code)) => {
+                    request.respond(200, "text/html", SUCCEEDED_PAGE);
+                    return Ok(code);
+                }
+                Some(Err(reason)) => {
+                    request.respond(200, "text/html", FAILED_PAGE);
+                    return Err(reason);
+                }
+            }
+        }
+        Err("the loopback server was closed".to_owned())
+    }
+
+    async fn exchange_code(&self, code: &str) -> Result<Token, String> {
+        let mut response = make_request(&auth_host(), "/oauth/token", HttpMethod::Post)
+            .set_json_body(&json!({
+                "client_id": CLIENT_ID,
+                "grant_type": "authorization_code",
+                "code": code,
+                "code_verifier": self.verifier,
+                "redirect_uri": self.redirect_uri(),
+            }))
+            .send()
+            .await
+            .map_err(|err| error_message(&err))?;
+        let status_code = response.status_code();
+        let data = response.text().await;
+        #[cfg(debug_assertions)]
+        console::log_str(&format!("token exchange response: {data}"));
+        if status_code != 200 {
+            return Err(format!("server returned status code {status_code}"));
+        }
+
+        let response =
+            serde_json::from_str::<TokenResponse>(&data).map_err(|err| err.to_string())?;
+        Ok(Token {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            challenge: base64_encode(sha256(&self.verifier)),
+            expires_at: Some((Utc::now() + Duration::seconds(response.expires_in)).timestamp()),
+        })
+    }
+}
+
+/// Returns the authorization code of the redirect, or the error reported by
+/// the auth server. `None` if the redirect is not of the flow with
+/// `expected_state`.
+fn parse_redirect(
+    mut params: HashMap<String, String>,
+    expected_state: &str,
+) -> Option<Result<String, String>> {
+    if params.get("state").map(String::as_str) != Some(expected_state) {
+        return None;
+    }
+    Some(params.remove("code").ok_or_else(|| {
+        params
+            .remove("error_description")
+            .or_else(|| params.remove("error"))
+            .unwrap_or_else(|| "no authorization code received".to_owned())
+    }))
+}
+
+/// Decodes the parameters of the query, only the first value of a repeated
+/// key is kept.
+fn parse_query(query: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        params
+            .entry(key.into_owned())
+            .or_insert_with(|| value.into_owned());
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        let params = parse_query("code=a%2Fb%3D&state=x+y%20z&empty=&flag");
+        assert_eq!(params["code"], "a/b=");
+        assert_eq!(params["state"], "x y z");
+        assert_eq!(params["empty"], "");
+        assert_eq!(params["flag"], "");
+        assert!(!params.contains_key("error"));
+
+        // A repeated key can't override the first value.
+        let params = parse_query("state=expected&state=forged");
+        assert_eq!(params["state"], "expected");
+
+        assert!(parse_query("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_redirect() {
+        let redirect = |query| parse_redirect(parse_query(query), "s1");
+        assert_eq!(redirect("code=abc&state=s1"), Some(Ok("abc".to_owned())));
+
+        // Redirects of other flows are ignored.
+        assert_eq!(redirect("code=abc&state=s2"), None);
+        assert_eq!(redirect("code=abc"), None);
+
+        assert_eq!(
+            redirect("error=access_denied&error_description=User+cancelled&state=s1"),
+            Some(Err("User cancelled".to_owned()))
+        );
+        assert_eq!(
+            redirect("error=access_denied&state=s1"),
+            Some(Err("access_denied".to_owned()))
+        );
+        assert_eq!(
+            redirect("state=s1"),
+            Some(Err("no authorization code received".to_owned()))
+        );
+    }
+}
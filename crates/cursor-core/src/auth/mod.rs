@@ -1,19 +1,29 @@
+mod account;
+mod loopback;
+mod sign_in;
+pub mod status;
+mod store;
 pub mod token;
 
-use std::future::IntoFuture;
+pub(crate) use self::store::active_account_name;
+
+use std::{cell::RefCell, collections::HashMap, future::Future};
 
 use base64::Engine;
+use chrono::{Duration, Utc};
 use futures::{
-    future::{select, Either},
-    StreamExt,
+    future::{LocalBoxFuture, Shared},
+    FutureExt,
+};
+use node_bridge::{
+    bindings::AbortSignal,
+    http_client::{HttpMethod, HttpRequest},
+    prelude::*,
 };
-use gloo::timers::future::IntervalStream;
-use node_bridge::{bindings::AbortSignal, futures::Defer, http_client::HttpMethod, prelude::*};
 use rand::RngCore;
 use serde::Deserialize;
 use serde_json::json;
 use sha2::Digest;
-use uuid::Uuid;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::{future_to_promise, spawn_local};
 
@@ -22,13 +32,20 @@ use crate::{
         progress::Progress, progress_location::ProgressLocation, progress_options::ProgressOptions,
     },
     context::get_extension_context,
-    request::{make_request, JsonSendable, API2_HOST},
+    model_registry,
+    request::{auth_host, error_message, make_request, JsonSendable},
+    services::chat::reset_all_chats,
 };
 
-use self::token::Token;
+use self::{
+    sign_in::{SignInMethod, SignInState},
+    status::AuthStatus,
+    token::Token,
+};
 
-const AUTH_TOKEN_KEY: &str = "auth_token";
 const CLIENT_ID: &str = "KbZUR41cY7W6zRSdpSUJ7I7mLYBKOCmB";
+/// How long before the access token expires we start refreshing it.
+const REFRESH_MARGIN_SECS: i64 = 5 * 60;
 
 fn random_bytes() -> Vec<u8> {
     let mut rng = rand::thread_rng();
@@ -57,32 +74,21 @@ where
     hasher.finalize().to_vec()
 }
 
+/// Signs in to a Cursor account and makes it the active account.
+///
+/// Signing in to an existing account name replaces its token. With
+/// `prefer_loopback`, the browser redirects to a local server instead of
+/// being polled for, see [`loopback`].
 #[wasm_bindgen(js_name = signIn)]
-pub async fn sign_in() {
+pub async fn sign_in(account_name: Option<String>, prefer_loopback: bool) {
     let context = get_extension_context();
-    let storage = context.storage();
-    if storage.get(AUTH_TOKEN_KEY).is_some() {
-        // If there is already an authentication token, it means that the user has logged in
-        // and does not need to log in again.
-        context
-            .show_information_message("You have already logged in.", js_sys::Array::new())
-            .await;
-        return;
-    }
-
-    let uuid = Uuid::new_v4().to_string();
-    let verifier = base64_encode(random_bytes());
-    let challenge = base64_encode(sha256(verifier.clone()));
-
-    let login_url = format!(
-        "https://cursor.sh/loginDeepControl?challenge={challenge}&uuid={}",
-        uuid.clone()
-    );
+    let account_name = account_name.unwrap_or_else(|| store::DEFAULT_ACCOUNT.to_owned());
+    let flow = SignInMethod::new(prefer_loopback).await;
 
     // The API of VSCode does not allow us to obtain the execution result of the 'vscode.open' command,
     // so we cannot determine whether the user has confirmed to open url.
     context
-        .execute_command1("vscode.open", JsValue::from_str(&login_url))
+        .execute_command1("vscode.open", JsValue::from_str(&flow.login_url()))
         .await;
 
     context
@@ -92,24 +98,27 @@ pub async fn sign_in() {
                 title: Some("Waiting for sign in / sign up...".to_owned()),
                 cancellable: true,
             },
-            closure_once!(|_progress: Progress, abort_signal: AbortSignal| {
+            closure_once!(|progress: Progress, abort_signal: AbortSignal| {
                 future_to_promise(async move {
-                    Ok(
-                        if let Some(token) = polling(&uuid, &verifier, abort_signal).await? {
-                            storage.update(AUTH_TOKEN_KEY, Some(&token));
-                            spawn_local(async move {
-                                get_extension_context()
-                                    .show_information_message(
-                                        "You have successfully logged in.",
-                                        js_sys::Array::new(),
-                                    )
-                                    .await;
-                            });
-                            JsValue::from_str(&token)
-                        } else {
-                            JsValue::null()
-                        },
-                    )
+                    let message = match flow.run(&progress, abort_signal).await {
+                        SignInState::Succeeded(token) => {
+                            store::save_token(&account_name, &token).await?;
+                            store::set_active_account(&account_name);
+                            model_registry::clear_cache();
+                            format!("You have successfully logged in as \"{account_name}\".")
+                        }
+                        SignInState::Expired => {
+                            "The sign in request has expired, please try again.".to_owned()
+                        }
+                        SignInState::Failed(reason) => format!("Failed to sign in: {reason}"),
+                        _ => return Ok(JsValue::null()),
+                    };
+                    spawn_local(async move {
+                        get_extension_context()
+                            .show_information_message(&message, js_sys::Array::new())
+                            .await;
+                    });
+                    Ok(JsValue::null())
                 })
             })
             .into_js_value()
@@ -118,86 +127,88 @@ pub async fn sign_in() {
         .await;
 }
 
-async fn polling(
-    uuid: &str,
-    verifier: &str,
-    abort_signal: AbortSignal,
-) -> Result<Option<String>, JsValue> {
-    let defer_abort = Defer::new();
-    let defer_abort_clone = defer_abort.clone();
-    abort_signal.add_event_listener(
-        "abort",
-        closure_once!(|| {
-            defer_abort_clone.resolve(JsValue::null());
-        })
-        .into_js_value(),
-    );
-
-    let mut interval = IntervalStream::new(1000);
-    loop {
-        let defer_abort_future = defer_abort.clone().into_future();
-        if let Either::Left(_) = select(defer_abort_future, interval.next()).await {
-            return Ok(None);
-        }
-        let Ok(mut response) = make_request(
-            API2_HOST,
-            &format!("/auth/poll?uuid={uuid}&verifier={verifier}"),
-            HttpMethod::Get,
+/// Signs out of the given account, or the active one if not specified.
+///
+/// The refresh token is revoked on the auth server, the account is removed
+/// locally even if the revocation fails.
+#[wasm_bindgen(js_name = signOut)]
+pub async fn sign_out(account_name: Option<String>) -> Result<(), JsValue> {
+    let active_account_name = store::load_active_account().await.map(|(name, _)| name);
+    let Some((account_name, is_active)) = sign_out_target(account_name, active_account_name) else {
+        return Ok(());
+    };
+
+    let revocation = match store::delete_account(&account_name).await? {
+        Some(token) => revoke_token(&token).await,
+        None => Ok(()),
+    };
+    if is_active {
+        // Drop the conversation held on behalf of the signed-out account.
+        reset_all_chats();
+        model_registry::clear_cache();
+    }
+
+    get_extension_context()
+        .show_information_message(
+            &sign_out_message(&account_name, &revocation),
+            js_sys::Array::new(),
         )
-        .send()
-        .await
-        else {
-            continue;
-        };
-        if response.status_code() != 200 {
-            // If the request fails, it means that the server is not ready yet,
-            // so we need to continue polling.
-            continue;
-        }
-        let data = response.text().await;
-
-        #[cfg(debug_assertions)]
-        console::log_str(&data);
-        match serde_json::from_str::<serde_json::Value>(&data).and_then(|value| {
-            if value.is_null() {
-                Ok(false)
-            } else {
-                serde_json::from_str::<Token>(&data).map(|_| true)
-            }
-        }) {
-            Ok(flag) => {
-                if !flag {
-                    continue;
-                }
-                return Ok(Some(data));
-            }
-            Err(err) => {
-                let js_error = JsError::new(&err.to_string());
-                let error = js_error.into();
-                #[cfg(debug_assertions)]
-                console::error1(&error);
-                return Err(error);
-            }
+        .await;
+    Ok(())
+}
+
+/// Returns the account to sign out of, and whether it's the active one.
+fn sign_out_target(
+    account_name: Option<String>,
+    active_account_name: Option<String>,
+) -> Option<(String, bool)> {
+    match (account_name, active_account_name) {
+        (Some(account_name), active_account_name) => {
+            let is_active = active_account_name.as_ref() == Some(&account_name);
+            Some((account_name, is_active))
         }
+        (None, active_account_name) => active_account_name.map(|name| (name, true)),
     }
 }
 
-#[wasm_bindgen(js_name = signOut)]
-pub fn sign_out() {
-    let context = get_extension_context();
-    context.storage().update(AUTH_TOKEN_KEY, None);
-    spawn_local(async move {
-        context
-            .show_information_message("You have successfully logged out.", js_sys::Array::new())
-            .await;
-    });
+fn sign_out_message(account_name: &str, revocation: &Result<(), String>) -> String {
+    match revocation {
+        Ok(_) => format!("You have successfully logged out of \"{account_name}\"."),
+        Err(reason) => format!(
+            "You have logged out of \"{account_name}\" on this device, \
+            but the session could not be revoked on the server: {reason}"
+        ),
+    }
+}
+
+/// Revokes the refresh token on the auth server, so that it can no longer
+/// be used to obtain new access tokens.
+async fn revoke_token(token: &Token) -> Result<(), String> {
+    let response = revoke_request(&auth_host(), token)
+        .send()
+        .await
+        .map_err(|err| error_message(&err))?;
+    revocation_result(response.status_code())
+}
+
+fn revoke_request(auth_host: &str, token: &Token) -> HttpRequest {
+    make_request(auth_host, "/oauth/revoke", HttpMethod::Post).set_json_body(&json!({
+        "client_id": CLIENT_ID,
+        "token": token.refresh_token,
+    }))
+}
+
+fn revocation_result(status_code: u16) -> Result<(), String> {
+    match status_code {
+        200 => Ok(()),
+        status_code => Err(format!("server returned status code {status_code}")),
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 struct RefreshResponse {
     pub access_token: String,
-    #[serde(rename = "expires_in")]
-    pub _expires_in: u64,
+    pub expires_in: i64,
     #[serde(rename = "scope")]
     pub _scope: String,
     #[serde(rename = "token_type")]
@@ -208,43 +219,118 @@ struct RefreshResponse {
 
 #[wasm_bindgen(js_name = refreshToken)]
 pub async fn refresh() -> Result<(), JsValue> {
-    if let Some(mut token) = account_token() {
-        let response = make_request("cursor.us.auth0.com", "/oauth/token", HttpMethod::Post)
-            .set_json_body(&json!({
-                "client_id": CLIENT_ID,
-                "grant_type": "refresh_token",
-                "refresh_token": token.refresh_token,
-            }))
-            .send()
-            .await?
-            .text()
-            .await;
-        #[cfg(debug_assertions)]
-        console::log_str(&format!("refresh token response: {response}"));
-        let access_token = serde_json::from_str::<RefreshResponse>(&response)
-            .map_err(JsError::from)?
-            .access_token;
-        token.access_token = access_token;
-
-        let context = get_extension_context();
-        let storage = context.storage();
-        storage.update(
-            AUTH_TOKEN_KEY,
-            Some(&serde_json::to_string(&token).map_err(JsError::from)?),
-        );
+    if account_token().await.is_some() {
+        refresh_account_token().await?;
     }
     Ok(())
 }
 
-pub fn account_token() -> Option<Token> {
-    get_extension_context()
-        .storage()
-        .get(AUTH_TOKEN_KEY)
-        .and_then(|token| serde_json::from_str(&token).ok())
+type RefreshFuture = Shared<LocalBoxFuture<'static, Result<Token, JsValue>>>;
+
+thread_local! {
+    static INFLIGHT_REFRESHES: RefCell<HashMap<String, RefreshFuture>> = RefCell::new(HashMap::new());
+}
+
+/// Refreshes the access token of the active account and persists it.
+///
+/// Concurrent callers share a single in-flight refresh request per account,
+/// so a burst of unauthenticated responses only hits the auth server once.
+pub async fn refresh_account_token() -> Result<Token, JsValue> {
+    let Some((account_name, _)) = store::load_active_account().await else {
+        return Err(JsError::new("Not signed in").into());
+    };
+    join_refresh(&account_name, |account_name| async move {
+        request_refreshed_token(&account_name).await
+    })
+    .await
+}
+
+/// Returns the in-flight refresh of the account, or starts a new one with
+/// `refresh`.
+fn join_refresh<F>(account_name: &str, refresh: impl FnOnce(String) -> F) -> RefreshFuture
+where
+    F: Future<Output = Result<Token, JsValue>> + 'static,
+{
+    INFLIGHT_REFRESHES.with(|inflight| {
+        inflight
+            .borrow_mut()
+            .entry(account_name.to_owned())
+            .or_insert_with(|| {
+                let account_name = account_name.to_owned();
+                let request = refresh(account_name.clone());
+                async move {
+                    let result = request.await;
+                    // Allow the next caller to start a new refresh.
+                    INFLIGHT_REFRESHES.with(|inflight| inflight.borrow_mut().remove(&account_name));
+                    result
+                }
+                .boxed_local()
+                .shared()
+            })
+            .clone()
+    })
+}
+
+async fn request_refreshed_token(account_name: &str) -> Result<Token, JsValue> {
+    let Some(mut token) = store::load_accounts().await.remove(account_name) else {
+        return Err(JsError::new(&format!("Account \"{account_name}\" does not exist")).into());
+    };
+
+    let mut response = make_request(&auth_host(), "/oauth/token", HttpMethod::Post)
+        .set_json_body(&json!({
+            "client_id": CLIENT_ID,
+            "grant_type": "refresh_token",
+            "refresh_token": token.refresh_token,
+        }))
+        .send()
+        .await?;
+    let status_code = response.status_code();
+    let data = response.text().await;
+    #[cfg(debug_assertions)]
+    console::log_str(&format!("refresh token response: {data}"));
+    if status_code != 200 {
+        return Err(js_sys::Error::new(&format!(
+            "Failed to refresh token, server returned status code {status_code}"
+        ))
+        .into());
+    }
+
+    let refreshed = serde_json::from_str::<RefreshResponse>(&data).map_err(JsError::from)?;
+    token.access_token = refreshed.access_token;
+    token.expires_at = Some((Utc::now() + Duration::seconds(refreshed.expires_in)).timestamp());
+
+    store::save_token(account_name, &token).await?;
+    Ok(token)
+}
+
+/// Returns the token of the signed-in account, refreshing it beforehand if
+/// the access token is about to expire.
+///
+/// The stale token is returned if the refresh fails, so that the caller can
+/// still decide how to handle the unauthenticated response.
+pub async fn valid_account_token() -> Option<Token> {
+    let token = account_token().await?;
+    if !token.expires_within(Duration::seconds(REFRESH_MARGIN_SECS)) {
+        return Some(token);
+    }
+    Some(refresh_account_token().await.unwrap_or(token))
+}
+
+#[wasm_bindgen(js_name = authStatus)]
+pub async fn auth_status() -> AuthStatus {
+    store::load_active_account().await.into()
+}
+
+pub async fn account_token() -> Option<Token> {
+    store::load_token().await
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+
+    use futures::{executor::block_on, future};
+
     use super::*;
 
     #[test]
@@ -264,6 +350,92 @@ mod tests {
         assert_eq!(encoded, "qR50NkpXtkDPJTf3ICZ6LpSQA4VbuNCSN9yz2QpN1MU");
     }
 
+    #[test]
+    fn test_join_refresh() {
+        let requests = Cell::new(0);
+        let refresh = |access_token: &'static str| {
+            |_| {
+                requests.set(requests.get() + 1);
+                future::ok(Token {
+                    access_token: access_token.to_owned(),
+                    refresh_token: "refresh".to_owned(),
+                    challenge: "challenge".to_owned(),
+                    expires_at: None,
+                })
+            }
+        };
+
+        // Concurrent refreshes of the same account share one request.
+        let first = join_refresh("default", refresh("first"));
+        let second = join_refresh("default", refresh("second"));
+        let other = join_refresh("other", refresh("other"));
+        assert_eq!(requests.get(), 2);
+        let (first, second, other) = block_on(future::join3(first, second, other));
+        assert_eq!(first.unwrap().access_token, "first");
+        assert_eq!(second.unwrap().access_token, "first");
+        assert_eq!(other.unwrap().access_token, "other");
+
+        // A refresh after the previous one completes makes a new request.
+        let third = block_on(join_refresh("default", refresh("third")));
+        assert_eq!(third.unwrap().access_token, "third");
+        assert_eq!(requests.get(), 3);
+    }
+
+    #[test]
+    fn test_sign_out_target() {
+        let name = |name: &str| Some(name.to_owned());
+        assert_eq!(sign_out_target(None, None), None);
+        assert_eq!(
+            sign_out_target(None, name("work")),
+            Some(("work".to_owned(), true))
+        );
+        assert_eq!(
+            sign_out_target(name("work"), name("work")),
+            Some(("work".to_owned(), true))
+        );
+        // Signing out of another account keeps the chats of the active one.
+        assert_eq!(
+            sign_out_target(name("personal"), name("work")),
+            Some(("personal".to_owned(), false))
+        );
+        assert_eq!(
+            sign_out_target(name("personal"), None),
+            Some(("personal".to_owned(), false))
+        );
+    }
+
+    #[test]
+    fn test_revoke_token() {
+        let token = Token {
+            access_token: "access".to_owned(),
+            refresh_token: "refresh".to_owned(),
+            challenge: "challenge".to_owned(),
+            expires_at: None,
+        };
+        let request = revoke_request("auth.example.com", &token);
+        assert_eq!(request.url(), "https://auth.example.com/oauth/revoke");
+        assert_eq!(request.method(), HttpMethod::Post);
+        let body = serde_json::from_slice::<serde_json::Value>(request.body().unwrap()).unwrap();
+        assert_eq!(body, json!({ "client_id": CLIENT_ID, "token": "refresh" }));
+
+        assert_eq!(revocation_result(200), Ok(()));
+        let revocation = revocation_result(503);
+        assert_eq!(
+            revocation,
+            Err("server returned status code 503".to_owned())
+        );
+        // The account is signed out locally even if the revocation fails.
+        assert_eq!(
+            sign_out_message("work", &revocation),
+            "You have logged out of \"work\" on this device, but the session \
+            could not be revoked on the server: server returned status code 503"
+        );
+        assert_eq!(
+            sign_out_message("work", &Ok(())),
+            "You have successfully logged out of \"work\"."
+        );
+    }
+
     #[test]
     fn test_sha256() {
         let v = "qR50NkpXtkDPJTf3ICZ6LpSQA4VbuNCSN9yz2QpN1MU";
@@ -0,0 +1,345 @@
+use std::future::IntoFuture;
+
+use chrono::{DateTime, Duration, Utc};
+use futures::future::{select, Either};
+use gloo::timers::future::TimeoutFuture;
+use node_bridge::{bindings::AbortSignal, futures::Defer, http_client::HttpMethod, prelude::*};
+use uuid::Uuid;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    bindings::progress::Progress,
+    request::{make_request, API2_HOST},
+};
+
+use super::{base64_encode, loopback::LoopbackSignIn, random_bytes, sha256, token::Token};
+
+/// How long the server keeps a sign in challenge alive.
+const CHALLENGE_LIFETIME_SECS: i64 = 10 * 60;
+const POLL_INTERVAL_MS: u32 = 1000;
+const MAX_BACKOFF_MS: u32 = 30 * 1000;
+/// The flow fails after this many errors in a row.
+const MAX_CONSECUTIVE_ERRORS: u32 = 8;
+
+#[derive(Debug, Clone)]
+pub enum SignInState {
+    /// The login page has been opened, no poll has been sent yet.
+    WaitingForBrowser,
+    /// Polling the server, `errors` is the number of consecutive failed polls.
+    Polling {
+        errors: u32,
+    },
+    Succeeded(Token),
+    /// The challenge expired before the user finished signing in.
+    Expired,
+    Cancelled,
+    Failed(String),
+}
+
+impl SignInState {
+    fn progress_message(&self) -> Option<String> {
+        match self {
+            SignInState::WaitingForBrowser => {
+                Some("Complete the sign in in your browser.".to_owned())
+            }
+            SignInState::Polling { errors: 0 } => {
+                Some("Waiting for the sign in to complete...".to_owned())
+            }
+            SignInState::Polling { errors } => Some(format!(
+                "Cannot reach the server, retrying... (attempt {})",
+                errors + 1
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PollOutcome {
+    /// The user has not finished signing in yet.
+    Pending,
+    Token(Token),
+    Error(String),
+}
+
+pub struct SignInFlow {
+    uuid: String,
+    verifier: String,
+    state: SignInState,
+    deadline: DateTime<Utc>,
+}
+
+impl SignInFlow {
+    pub fn new() -> Self {
+        Self {
+            uuid: Uuid::new_v4().to_string(),
+            verifier: base64_encode(random_bytes()),
+            state: SignInState::WaitingForBrowser,
+            deadline: Utc::now() + Duration::seconds(CHALLENGE_LIFETIME_SECS),
+        }
+    }
+
+    pub fn login_url(&self) -> String {
+        let challenge = base64_encode(sha256(&self.verifier));
+        format!(
+            "https://cursor.sh/loginDeepControl?challenge={challenge}&uuid={}",
+            self.uuid
+        )
+    }
+
+    /// Polls the server until the flow reaches a final state.
+    ///
+    /// State changes are reported through `progress`.
+    pub async fn run(mut self, progress: &Progress, abort_signal: AbortSignal) -> SignInState {
+        let defer_abort = Defer::new();
+        let defer_abort_clone = defer_abort.clone();
+        abort_signal.add_event_listener(
+            "abort",
+            closure_once!(|| {
+                defer_abort_clone.resolve(JsValue::null());
+            })
+            .into_js_value(),
+        );
+
+        self.report(progress);
+        let mut delay = POLL_INTERVAL_MS;
+        loop {
+            let defer_abort_future = defer_abort.clone().into_future();
+            if let Either::Left(_) = select(defer_abort_future, TimeoutFuture::new(delay)).await {
+                return SignInState::Cancelled;
+            }
+
+            let outcome = self.poll().await;
+            match self.advance(outcome, Utc::now()) {
+                Some(next_delay) => delay = next_delay,
+                None => return self.state,
+            }
+            self.report(progress);
+        }
+    }
+
+    fn report(&self, progress: &Progress) {
+        if let Some(message) = self.state.progress_message() {
+            progress.report(&message);
+        }
+    }
+
+    async fn poll(&self) -> PollOutcome {
+        let response = make_request(
+            API2_HOST,
+            &format!("/auth/poll?uuid={}&verifier={}", self.uuid, self.verifier),
+            HttpMethod::Get,
+        )
+        .send()
+        .await;
+        let mut response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                return PollOutcome::Error(err.as_string().unwrap_or("network error".to_owned()))
+            }
+        };
+        let status_code = response.status_code();
+        let data = if status_code == 200 {
+            response.text().await
+        } else {
+            String::new()
+        };
+
+        #[cfg(debug_assertions)]
+        console::log_str(&format!("poll: {status_code} {data}"));
+        poll_outcome(status_code, &data)
+    }
+
+    /// Moves to the next state according to the outcome of a poll.
+    ///
+    /// Returns the delay in milliseconds before the next poll, or `None`
+    /// if the flow is finished.
+    fn advance(&mut self, outcome: PollOutcome, now: DateTime<Utc>) -> Option<u32> {
+        let errors = match &self.state {
+            SignInState::Polling { errors } => *errors,
+            _ => 0,
+        };
+        self.state = match outcome {
+            PollOutcome::Token(token) => SignInState::Succeeded(token),
+            _ if now >= self.deadline => SignInState::Expired,
+            PollOutcome::Pending => SignInState::Polling { errors: 0 },
+            PollOutcome::Error(_) if errors + 1 < MAX_CONSECUTIVE_ERRORS => {
+                SignInState::Polling { errors: errors + 1 }
+            }
+            PollOutcome::Error(reason) => SignInState::Failed(reason),
+        };
+
+        match self.state {
+            SignInState::Polling { errors } => Some(backoff_delay(errors)),
+            _ => None,
+        }
+    }
+}
+
+impl Default for SignInFlow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The way of receiving the token after the user signs in in the browser.
+pub enum SignInMethod {
+    /// Redirect to a loopback server, see [`LoopbackSignIn`].
+    Loopback(LoopbackSignIn),
+    /// Poll the Cursor server with a challenge, see [`SignInFlow`].
+    Polling(SignInFlow),
+}
+
+impl SignInMethod {
+    /// Prepares the sign in, falling back to polling if the loopback server
+    /// cannot be started.
+    pub async fn new(prefer_loopback: bool) -> Self {
+        if prefer_loopback {
+            match LoopbackSignIn::start().await {
+                Ok(sign_in) => return SignInMethod::Loopback(sign_in),
+                Err(err) => console::warn2(
+                    &"Cannot start the loopback server, falling back to polling: ".into(),
+                    &err,
+                ),
+            }
+        }
+        SignInMethod::Polling(SignInFlow::new())
+    }
+
+    pub fn login_url(&self) -> String {
+        match self {
+            SignInMethod::Loopback(sign_in) => sign_in.login_url(),
+            SignInMethod::Polling(flow) => flow.login_url(),
+        }
+    }
+
+    pub async fn run(self, progress: &Progress, abort_signal: AbortSignal) -> SignInState {
+        match self {
+            SignInMethod::Loopback(sign_in) => sign_in.run(progress, abort_signal).await,
+            SignInMethod::Polling(flow) => flow.run(progress, abort_signal).await,
+        }
+    }
+}
+
+/// Interprets the response to a poll.
+///
+/// The server answers with 404, or `null`, until the browser has completed
+/// the sign in, so neither counts as an error. Any other status, or a body
+/// that is not a token, does.
+fn poll_outcome(status_code: u16, data: &str) -> PollOutcome {
+    match status_code {
+        200 => {}
+        404 => return PollOutcome::Pending,
+        status_code => {
+            return PollOutcome::Error(format!("server returned status code {status_code}"))
+        }
+    }
+    match serde_json::from_str::<Option<Token>>(data) {
+        Ok(Some(token)) => PollOutcome::Token(token),
+        Ok(None) => PollOutcome::Pending,
+        Err(err) => PollOutcome::Error(err.to_string()),
+    }
+}
+
+/// Returns the poll interval after the given number of consecutive errors,
+/// doubling with each error.
+fn backoff_delay(errors: u32) -> u32 {
+    POLL_INTERVAL_MS
+        .saturating_mul(1 << errors.min(16))
+        .min(MAX_BACKOFF_MS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_token() -> Token {
+        Token {
+            access_token: "access".to_owned(),
+            refresh_token: "refresh".to_owned(),
+            challenge: "challenge".to_owned(),
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        assert_eq!(backoff_delay(0), 1000);
+        assert_eq!(backoff_delay(1), 2000);
+        assert_eq!(backoff_delay(3), 8000);
+        assert_eq!(backoff_delay(10), MAX_BACKOFF_MS);
+        assert_eq!(backoff_delay(u32::MAX), MAX_BACKOFF_MS);
+    }
+
+    #[test]
+    fn test_poll_outcome() {
+        assert!(matches!(poll_outcome(404, ""), PollOutcome::Pending));
+        assert!(matches!(poll_outcome(500, ""), PollOutcome::Error(_)));
+        assert!(matches!(poll_outcome(401, ""), PollOutcome::Error(_)));
+        assert!(matches!(poll_outcome(200, "null"), PollOutcome::Pending));
+        assert!(matches!(
+            poll_outcome(
+                200,
+                r#"{"accessToken":"a","refreshToken":"r","challenge":"c"}"#
+            ),
+            PollOutcome::Token(_)
+        ));
+        assert!(matches!(poll_outcome(200, "<html>"), PollOutcome::Error(_)));
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut flow = SignInFlow::new();
+        let now = Utc::now();
+
+        assert_eq!(flow.advance(PollOutcome::Pending, now), Some(1000));
+        assert_eq!(
+            flow.advance(PollOutcome::Error("oops".to_owned()), now),
+            Some(2000)
+        );
+        // A successful poll resets the backoff.
+        assert_eq!(flow.advance(PollOutcome::Pending, now), Some(1000));
+        assert_eq!(flow.advance(PollOutcome::Token(make_token()), now), None);
+        assert!(matches!(flow.state, SignInState::Succeeded(_)));
+    }
+
+    #[test]
+    fn test_advance_failed() {
+        let mut flow = SignInFlow::new();
+        let now = Utc::now();
+        for _ in 0..MAX_CONSECUTIVE_ERRORS - 1 {
+            assert!(flow
+                .advance(PollOutcome::Error("oops".to_owned()), now)
+                .is_some());
+        }
+        assert_eq!(
+            flow.advance(PollOutcome::Error("oops".to_owned()), now),
+            None
+        );
+        assert!(matches!(flow.state, SignInState::Failed(_)));
+    }
+
+    #[test]
+    fn test_advance_server_errors() {
+        let mut flow = SignInFlow::new();
+        let now = Utc::now();
+        // A server outage backs off and eventually fails the flow.
+        let delays = (0..MAX_CONSECUTIVE_ERRORS)
+            .map(|_| flow.advance(poll_outcome(500, ""), now))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays[..4],
+            [Some(2000), Some(4000), Some(8000), Some(16000)]
+        );
+        assert_eq!(delays.last(), Some(&None));
+        assert!(matches!(flow.state, SignInState::Failed(_)));
+    }
+
+    #[test]
+    fn test_advance_expired() {
+        let mut flow = SignInFlow::new();
+        let later = Utc::now() + Duration::seconds(CHALLENGE_LIFETIME_SECS + 1);
+        assert_eq!(flow.advance(PollOutcome::Pending, later), None);
+        assert!(matches!(flow.state, SignInState::Expired));
+    }
+}
@@ -0,0 +1,35 @@
+use wasm_bindgen::prelude::*;
+
+use super::token::Token;
+
+#[wasm_bindgen(getter_with_clone, js_name = AuthStatus)]
+#[derive(Debug, Clone)]
+pub struct AuthStatus {
+    #[wasm_bindgen(js_name = signedIn)]
+    pub signed_in: bool,
+
+    /// The name of the account used in the current workspace.
+    pub account: Option<String>,
+
+    /// Whether the access token has not expired yet.
+    pub valid: bool,
+
+    /// The expiration time of the access token in milliseconds since
+    /// the Unix epoch, ready to be passed to `new Date()`.
+    #[wasm_bindgen(js_name = expiresAt)]
+    pub expires_at: Option<f64>,
+}
+
+impl From<Option<(String, Token)>> for AuthStatus {
+    fn from(account: Option<(String, Token)>) -> Self {
+        let token = account.as_ref().map(|(_, token)| token);
+        Self {
+            signed_in: token.is_some(),
+            valid: token.map(|t| !t.is_expired()).unwrap_or(false),
+            expires_at: token
+                .and_then(Token::expiry)
+                .map(|expiry| (expiry * 1000) as f64),
+            account: account.map(|(name, _)| name),
+        }
+    }
+}
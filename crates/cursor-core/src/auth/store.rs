@@ -0,0 +1,215 @@
+//! Persistence of account tokens.
+//!
+//! Tokens of all the signed-in accounts are kept in the secret storage of
+//! the editor, which is backed by the OS keychain. The name of the active
+//! account is kept in the global storage, and can be overridden per
+//! workspace.
+//!
+//! Older versions stored a single token as plain text in the global
+//! storage, it's moved over as the default account the first time the
+//! accounts are read.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use futures::lock::{Mutex, OwnedMutexGuard};
+use node_bridge::prelude::*;
+use wasm_bindgen::JsError;
+
+use crate::context::get_extension_context;
+
+use super::token::Token;
+
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+const ACCOUNTS_KEY: &str = "accounts";
+const ACTIVE_ACCOUNT_KEY: &str = "active_account";
+const WORKSPACE_ACCOUNT_KEY: &str = "workspace_account";
+const LEGACY_AUTH_TOKEN_KEY: &str = "auth_token";
+
+pub type Accounts = BTreeMap<String, Token>;
+
+thread_local! {
+    /// Held while the accounts are read and written back, the secret storage
+    /// is accessed across awaits so concurrent updates could be lost.
+    static ACCOUNTS_LOCK: Arc<Mutex<()>> = Arc::new(Mutex::new(()));
+}
+
+async fn lock_accounts() -> OwnedMutexGuard<()> {
+    ACCOUNTS_LOCK.with(Arc::clone).lock_owned().await
+}
+
+/// Returns the signed-in accounts, none if they cannot be read.
+pub async fn load_accounts() -> Accounts {
+    read_accounts().await.unwrap_or_else(|err| {
+        console::warn_str(&format!("Failed to read the accounts: {err}"));
+        Accounts::new()
+    })
+}
+
+/// Reads the accounts, failing if the stored data is unreadable so that it
+/// is not overwritten.
+async fn read_accounts() -> Result<Accounts, String> {
+    let context = get_extension_context();
+    let secrets = context.secrets();
+    if let Some(data) = secrets.get(ACCOUNTS_KEY).await {
+        return serde_json::from_str(&data).map_err(|err| err.to_string());
+    }
+
+    // One-time migration of the single token stored by older versions.
+    let storage = context.storage();
+    let legacy_token = match secrets.get(LEGACY_AUTH_TOKEN_KEY).await {
+        Some(data) => Some(data),
+        None => storage.get(LEGACY_AUTH_TOKEN_KEY),
+    };
+    let mut accounts = Accounts::new();
+    if let Some(token) = legacy_token.and_then(|data| serde_json::from_str(&data).ok()) {
+        accounts.insert(DEFAULT_ACCOUNT.to_owned(), token);
+        if save_accounts(&accounts).await.is_ok() {
+            secrets.delete(LEGACY_AUTH_TOKEN_KEY).await;
+            storage.update(LEGACY_AUTH_TOKEN_KEY, None);
+        }
+    }
+    Ok(accounts)
+}
+
+async fn save_accounts(accounts: &Accounts) -> Result<(), JsError> {
+    let data = serde_json::to_string(accounts)?;
+    get_extension_context()
+        .secrets()
+        .store(ACCOUNTS_KEY, &data)
+        .await;
+    Ok(())
+}
+
+/// Returns the name of the account used in the current workspace.
+///
+/// The account selected for the workspace takes precedence over the
+/// globally active one.
+pub fn active_account_name() -> Option<String> {
+    let context = get_extension_context();
+    context
+        .workspace_storage()
+        .get(WORKSPACE_ACCOUNT_KEY)
+        .or_else(|| context.storage().get(ACTIVE_ACCOUNT_KEY))
+}
+
+pub fn workspace_account_name() -> Option<String> {
+    get_extension_context()
+        .workspace_storage()
+        .get(WORKSPACE_ACCOUNT_KEY)
+}
+
+/// Returns the name and the token of the account used in the current
+/// workspace.
+///
+/// If no account is explicitly selected, or the selected one has been
+/// removed, it falls back to the default account or the first one.
+pub async fn load_active_account() -> Option<(String, Token)> {
+    let mut accounts = load_accounts().await;
+    let name = select_account(&accounts, active_account_name())?;
+    let token = accounts.remove(&name)?;
+    Some((name, token))
+}
+
+/// Returns the selected account if it exists, or the default account, or
+/// the first one.
+fn select_account(accounts: &Accounts, selected: Option<String>) -> Option<String> {
+    selected
+        .filter(|name| accounts.contains_key(name))
+        .or_else(|| {
+            accounts
+                .contains_key(DEFAULT_ACCOUNT)
+                .then(|| DEFAULT_ACCOUNT.to_owned())
+        })
+        .or_else(|| accounts.keys().next().cloned())
+}
+
+fn read_error(err: String) -> JsError {
+    JsError::new(&format!("Failed to read the accounts: {err}"))
+}
+
+pub async fn load_token() -> Option<Token> {
+    load_active_account().await.map(|(_, token)| token)
+}
+
+/// Stores the token of the given account, adding the account if it doesn't
+/// exist yet.
+pub async fn save_token(name: &str, token: &Token) -> Result<(), JsError> {
+    let _guard = lock_accounts().await;
+    let mut accounts = read_accounts().await.map_err(read_error)?;
+    accounts.insert(name.to_owned(), token.clone());
+    save_accounts(&accounts).await
+}
+
+/// Removes the account, returning its token if it existed.
+pub async fn delete_account(name: &str) -> Result<Option<Token>, JsError> {
+    let _guard = lock_accounts().await;
+    let mut accounts = read_accounts().await.map_err(read_error)?;
+    let token = accounts.remove(name);
+    save_accounts(&accounts).await?;
+
+    let context = get_extension_context();
+    let storage = context.storage();
+    if storage.get(ACTIVE_ACCOUNT_KEY).as_deref() == Some(name) {
+        storage.update(ACTIVE_ACCOUNT_KEY, None);
+    }
+    let workspace_storage = context.workspace_storage();
+    if workspace_storage.get(WORKSPACE_ACCOUNT_KEY).as_deref() == Some(name) {
+        workspace_storage.update(WORKSPACE_ACCOUNT_KEY, None);
+    }
+    Ok(token)
+}
+
+pub fn set_active_account(name: &str) {
+    get_extension_context()
+        .storage()
+        .update(ACTIVE_ACCOUNT_KEY, Some(name));
+}
+
+pub fn set_workspace_account(name: Option<&str>) {
+    get_extension_context()
+        .workspace_storage()
+        .update(WORKSPACE_ACCOUNT_KEY, name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_accounts(names: &[&str]) -> Accounts {
+        names
+            .iter()
+            .map(|name| {
+                let token = Token {
+                    access_token: "access".to_owned(),
+                    refresh_token: "refresh".to_owned(),
+                    challenge: "challenge".to_owned(),
+                    expires_at: None,
+                };
+                (name.to_string(), token)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_select_account() {
+        let accounts = make_accounts(&["alice", "bob", DEFAULT_ACCOUNT]);
+        assert_eq!(
+            select_account(&accounts, Some("bob".to_owned())).as_deref(),
+            Some("bob")
+        );
+        // A removed account falls back to the default one.
+        assert_eq!(
+            select_account(&accounts, Some("carol".to_owned())).as_deref(),
+            Some(DEFAULT_ACCOUNT)
+        );
+        assert_eq!(
+            select_account(&accounts, None).as_deref(),
+            Some(DEFAULT_ACCOUNT)
+        );
+
+        let accounts = make_accounts(&["bob", "alice"]);
+        assert_eq!(select_account(&accounts, None).as_deref(), Some("alice"));
+        assert_eq!(select_account(&Accounts::new(), None), None);
+    }
+}
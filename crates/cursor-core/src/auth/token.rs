@@ -1,3 +1,5 @@
+use base64::Engine;
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -6,4 +8,98 @@ pub struct Token {
     pub access_token: String,
     pub refresh_token: String,
     pub challenge: String,
+
+    /// The expiration time (Unix timestamp in seconds) reported by the
+    /// last token refresh. Only used when the access token itself does
+    /// not carry an `exp` claim.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+}
+
+impl Token {
+    /// Returns the expiration time of the access token as a Unix timestamp
+    /// in seconds, or `None` if it's unknown.
+    pub fn expiry(&self) -> Option<i64> {
+        jwt_expiry(&self.access_token).or(self.expires_at)
+    }
+
+    /// Returns `true` if the access token expires within the given duration
+    /// from now. Tokens with unknown expiry are treated as valid.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        self.expiry()
+            .map(|expiry| expiry <= (Utc::now() + duration).timestamp())
+            .unwrap_or(false)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_within(Duration::zero())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    exp: i64,
+}
+
+/// Decodes the `exp` claim from the payload of a JWT without verifying
+/// its signature.
+fn jwt_expiry(jwt: &str) -> Option<i64> {
+    let payload = jwt.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    serde_json::from_slice::<Claims>(&bytes)
+        .ok()
+        .map(|claims| claims.exp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_jwt(payload: &str) -> String {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        format!(
+            "{}.{}.signature",
+            engine.encode(r#"{"alg":"RS256","typ":"JWT"}"#),
+            engine.encode(payload)
+        )
+    }
+
+    fn make_token(access_token: String, expires_at: Option<i64>) -> Token {
+        Token {
+            access_token,
+            refresh_token: "refresh".to_owned(),
+            challenge: "challenge".to_owned(),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn test_jwt_expiry() {
+        let jwt = make_jwt(r#"{"sub":"auth0|user","exp":1700000000}"#);
+        assert_eq!(jwt_expiry(&jwt), Some(1700000000));
+        assert_eq!(jwt_expiry("not-a-jwt"), None);
+        assert_eq!(jwt_expiry(&make_jwt(r#"{"sub":"auth0|user"}"#)), None);
+    }
+
+    #[test]
+    fn test_expiry_fallback() {
+        let token = make_token("opaque".to_owned(), Some(42));
+        assert_eq!(token.expiry(), Some(42));
+        assert!(token.is_expired());
+
+        let token = make_token("opaque".to_owned(), None);
+        assert_eq!(token.expiry(), None);
+        assert!(!token.is_expired());
+    }
+
+    #[test]
+    fn test_expires_within() {
+        let exp = (Utc::now() + Duration::minutes(3)).timestamp();
+        let token = make_token(make_jwt(&format!(r#"{{"exp":{exp}}}"#)), None);
+        assert!(!token.is_expired());
+        assert!(token.expires_within(Duration::minutes(5)));
+        assert!(!token.expires_within(Duration::minutes(1)));
+    }
 }
@@ -4,13 +4,15 @@ use wasm_bindgen::prelude::*;
 
 use crate::{
     bindings::progress_options::ProgressOptions, model_configuration::ModelConfiguration,
-    storage::GlobalStorage,
+    secret_storage::SecretStorage, storage::GlobalStorage,
 };
 
 #[wasm_bindgen(typescript_custom_section)]
 const IEXTENSION_CONTEXT: &'static str = r#"
 interface IExtensionContext {
     get storage(): IGlobalStorage;
+    get secrets(): ISecretStorage;
+    get workspaceStorage(): IGlobalStorage;
     executeCommand(command: string, ...args: any[]): Thenable<any>;
     withProgress(options: RustProgressOptions, callback: () => Thenable<any>): Thenable<any>;
     showInformationMessage(message: string, items: string[]): Thenable<string | undefined>;
@@ -26,6 +28,12 @@ extern "C" {
     #[wasm_bindgen(method, structural, getter)]
     pub fn storage(this: &ExtensionContext) -> GlobalStorage;
 
+    #[wasm_bindgen(method, structural, getter)]
+    pub fn secrets(this: &ExtensionContext) -> SecretStorage;
+
+    #[wasm_bindgen(method, structural, getter, js_name = workspaceStorage)]
+    pub fn workspace_storage(this: &ExtensionContext) -> GlobalStorage;
+
     #[wasm_bindgen(method, structural, js_name = executeCommand)]
     pub async fn execute_command0(this: &ExtensionContext, command: &str) -> JsValue;
 
@@ -60,7 +68,7 @@ extern "C" {
 }
 
 thread_local! {
-    static EXTENSION_CONTEXT: RefCell<Option<ExtensionContext>> = RefCell::new(None);
+    static EXTENSION_CONTEXT: RefCell<Option<ExtensionContext>> = const { RefCell::new(None) };
 }
 
 #[wasm_bindgen(js_name = setExtensionContext)]
@@ -2,8 +2,10 @@ pub mod auth;
 mod bindings;
 pub mod context;
 pub mod model_configuration;
+pub mod model_registry;
 mod project;
 mod request;
+pub mod secret_storage;
 pub mod services;
 pub mod storage;
 
@@ -34,6 +36,16 @@ interface IResultStream {
 }
 "#;
 
+#[wasm_bindgen(typescript_custom_section)]
+const ICONTEXT_ITEM: &'static str = r#"
+interface IContextItem {
+    get filePath(): string;
+    get workspaceDirectory(): string | null;
+    get range(): ISelectionRange | null;
+    get contents(): string;
+}
+"#;
+
 #[wasm_bindgen(typescript_custom_section)]
 const IGENERATE_INPUT: &'static str = r#"
 interface IGenerateInput {
@@ -46,6 +58,7 @@ interface IGenerateInput {
     get resultStream(): IResultStream;
     get abortSignal(): AbortSignal;
     get languageId(): string;
+    get contextItems(): IContextItem[] | undefined;
 }
 "#;
 
@@ -93,6 +106,27 @@ extern "C" {
     pub fn end(this: &ResultStream);
 }
 
+#[wasm_bindgen]
+extern "C" {
+    /// A file or a range of it attached to the message by the user.
+    #[wasm_bindgen(typescript_type = "IContextItem")]
+    pub type ContextItem;
+
+    #[wasm_bindgen(method, getter, structural, js_name = filePath)]
+    pub fn file_path(this: &ContextItem) -> String;
+
+    /// The workspace folder of the file, `null` if it's outside the workspace.
+    #[wasm_bindgen(method, getter, structural, js_name = workspaceDirectory)]
+    pub fn workspace_directory(this: &ContextItem) -> Option<String>;
+
+    /// The range of the contents in the file, `null` for the whole file.
+    #[wasm_bindgen(method, getter, structural)]
+    pub fn range(this: &ContextItem) -> Option<SelectionRange>;
+
+    #[wasm_bindgen(method, getter, structural)]
+    pub fn contents(this: &ContextItem) -> String;
+}
+
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(typescript_type = "IGenerateInput")]
@@ -124,15 +158,28 @@ extern "C" {
 
     #[wasm_bindgen(method, getter, structural, js_name = languageId)]
     pub fn language_id(this: &GenerateInput) -> String;
+
+    #[wasm_bindgen(method, getter, structural, js_name = contextItems)]
+    pub fn context_items(this: &GenerateInput) -> Option<Vec<ContextItem>>;
 }
 
 impl GenerateInput {
+    /// Returns the path of the document relative to its workspace folder.
+    pub fn relative_file_path(&self) -> String {
+        let workspace_directory = self.workspace_directory();
+        services::workspace_path::relative_workspace_path(
+            &self.file_path(),
+            workspace_directory.as_deref(),
+            workspace_directory.as_deref(),
+        )
+    }
+
     pub fn file_dir(&self) -> String {
         let file_path = self.file_path();
-        return file_path
+        file_path
             .split('/')
             .take(file_path.split('/').count() - 1)
             .collect::<Vec<&str>>()
-            .join("/");
+            .join("/")
     }
 }
@@ -1,10 +1,25 @@
 use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{context::get_extension_context, model_registry};
 
 #[wasm_bindgen(typescript_custom_section)]
 const IMODEL_CONFIGURATION: &'static str = r#"
 interface IModelConfiguration {
     get apiKey(): string | null;
     get gptModel(): string;
+    get provider(): string;
+    get baseUrl(): string | null;
+    get localModel(): string;
+    get chatModel(): string | null;
+    get editModel(): string | null;
+    get generateModel(): string | null;
+    get projectModel(): string | null;
+    get temperature(): number | null;
+    get maxTokens(): number | null;
+    get stop(): string[];
+    get rulesFile(): string | null;
+    get authHost(): string | null;
 }
 "#;
 
@@ -16,6 +31,360 @@ extern "C" {
     #[wasm_bindgen(method, getter, structural, js_name = apiKey)]
     pub fn api_key(this: &ModelConfiguration) -> Option<String>;
 
+    /// The default model of all the features.
     #[wasm_bindgen(method, getter, structural, js_name = gptModel)]
     pub fn model_name(this: &ModelConfiguration) -> String;
+
+    #[wasm_bindgen(method, getter, structural, js_name = provider)]
+    fn provider_name(this: &ModelConfiguration) -> String;
+
+    /// The base URL of the provider API, e.g. `https://api.openai.com/v1`.
+    #[wasm_bindgen(method, getter, structural, js_name = baseUrl)]
+    pub fn base_url(this: &ModelConfiguration) -> Option<String>;
+
+    /// The model served by the local server, e.g. `codellama`.
+    #[wasm_bindgen(method, getter, structural, js_name = localModel)]
+    pub fn local_model(this: &ModelConfiguration) -> String;
+
+    #[wasm_bindgen(method, getter, structural, js_name = chatModel)]
+    fn chat_model(this: &ModelConfiguration) -> Option<String>;
+
+    #[wasm_bindgen(method, getter, structural, js_name = editModel)]
+    fn edit_model(this: &ModelConfiguration) -> Option<String>;
+
+    #[wasm_bindgen(method, getter, structural, js_name = generateModel)]
+    fn generate_model(this: &ModelConfiguration) -> Option<String>;
+
+    #[wasm_bindgen(method, getter, structural, js_name = projectModel)]
+    fn project_model(this: &ModelConfiguration) -> Option<String>;
+
+    #[wasm_bindgen(method, getter, structural, js_name = temperature)]
+    fn temperature(this: &ModelConfiguration) -> Option<f64>;
+
+    #[wasm_bindgen(method, getter, structural, js_name = maxTokens)]
+    fn max_tokens(this: &ModelConfiguration) -> Option<f64>;
+
+    #[wasm_bindgen(method, getter, structural, js_name = stop)]
+    fn stop(this: &ModelConfiguration) -> Vec<String>;
+
+    /// The file with the instructions of the project, see [`crate::services::rules`].
+    #[wasm_bindgen(method, getter, structural, js_name = rulesFile)]
+    pub fn rules_file(this: &ModelConfiguration) -> Option<String>;
+
+    /// The host of the OAuth server, e.g. `cursor.us.auth0.com`.
+    #[wasm_bindgen(method, getter, structural, js_name = authHost)]
+    pub fn auth_host(this: &ModelConfiguration) -> Option<String>;
+}
+
+/// Models served by the Cursor server.
+pub const CURSOR_MODELS: [&str; 2] = ["gpt-3.5-turbo", "gpt-4"];
+
+/// The backend that serves completions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    /// The Cursor server, the API key (if any) is forwarded to it.
+    Cursor,
+    /// An OpenAI-compatible `/chat/completions` endpoint, requested directly.
+    OpenAi,
+    /// A local Ollama or llama.cpp server, through its OpenAI-compatible
+    /// API, so that no code leaves the machine.
+    Local,
+}
+
+impl Provider {
+    fn from_name(name: &str) -> Self {
+        match name {
+            "openai" => Provider::OpenAi,
+            "local" => Provider::Local,
+            _ => Provider::Cursor,
+        }
+    }
+
+    /// Returns the models known to be served, or `None` if any model name
+    /// is accepted.
+    fn known_models(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Provider::Cursor => Some(&CURSOR_MODELS),
+            Provider::OpenAi | Provider::Local => None,
+        }
+    }
+
+    /// Returns the provider serving the feature, projects are only generated
+    /// by the Cursor server.
+    fn for_feature(self, feature: Feature) -> Self {
+        match feature {
+            Feature::Project => Provider::Cursor,
+            _ => self,
+        }
+    }
+}
+
+/// The features that can use different models.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Chat,
+    Edit,
+    Generate,
+    Project,
+}
+
+impl std::fmt::Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Feature::Chat => "chat",
+            Feature::Edit => "edit",
+            Feature::Generate => "generate",
+            Feature::Project => "project generation",
+        })
+    }
+}
+
+/// Parameters of sampling the output of the model.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerationParameters {
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u32>,
+    pub stop: Vec<String>,
+}
+
+/// The model to use for a feature, along with the generation parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSelection {
+    pub name: String,
+    pub parameters: GenerationParameters,
+}
+
+/// The limits follow the OpenAI API, which is the strictest of the
+/// supported backends.
+const MAX_TEMPERATURE: f64 = 2.0;
+const MAX_STOP_SEQUENCES: usize = 4;
+
+impl ModelConfiguration {
+    pub fn provider(&self) -> Provider {
+        Provider::from_name(&self.provider_name())
+    }
+
+    /// Returns the validated model and parameters for the feature.
+    ///
+    /// ## Errors
+    ///
+    /// Returns a message meant for the user if the model is unknown to the
+    /// provider or a parameter is out of range.
+    pub fn select_model(&self, feature: Feature) -> Result<ModelSelection, String> {
+        let provider = self.provider().for_feature(feature);
+        let feature_model = match feature {
+            Feature::Chat => self.chat_model(),
+            Feature::Edit => self.edit_model(),
+            Feature::Generate => self.generate_model(),
+            Feature::Project => self.project_model(),
+        };
+        let name = feature_model
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| match provider {
+                Provider::Local => self.local_model(),
+                _ => self.model_name(),
+            });
+        let max_tokens = match self.max_tokens() {
+            Some(max_tokens) if max_tokens.fract() != 0.0 || max_tokens < 0.0 => {
+                return Err(format!(
+                    "Max tokens must be a positive integer, got {max_tokens}"
+                ))
+            }
+            max_tokens => max_tokens.map(|max_tokens| max_tokens as u32),
+        };
+        let selection = ModelSelection {
+            name,
+            parameters: GenerationParameters {
+                temperature: self.temperature(),
+                max_tokens,
+                stop: self.stop(),
+            },
+        };
+        let cached_names = (provider == self.provider())
+            .then(|| model_registry::cached_models(self))
+            .flatten()
+            .map(|models| {
+                models
+                    .into_iter()
+                    .map(|model| model.name)
+                    .collect::<Vec<_>>()
+            });
+        let cached_names = cached_names
+            .as_ref()
+            .map(|names| names.iter().map(String::as_str).collect::<Vec<_>>());
+        validate_model(&selection, provider, cached_names.as_deref(), feature)?;
+        Ok(selection)
+    }
+
+    /// Same as [`ModelConfiguration::select_model`], but also shows the
+    /// error to the user.
+    pub fn select_model_or_report(&self, feature: Feature) -> Result<ModelSelection, JsValue> {
+        self.select_model(feature).map_err(|message| {
+            let error = JsError::new(&message).into();
+            spawn_local(async move {
+                get_extension_context()
+                    .show_information_message(
+                        &format!("Invalid model configuration: {message}"),
+                        js_sys::Array::new(),
+                    )
+                    .await;
+            });
+            error
+        })
+    }
+}
+
+/// Validates the selection against the models the backend reported, or the
+/// built-in list of the provider if the model is not among them, so that
+/// a stale cache doesn't block the requests.
+fn validate_model(
+    selection: &ModelSelection,
+    provider: Provider,
+    cached_models: Option<&[&str]>,
+    feature: Feature,
+) -> Result<(), String> {
+    let Some(cached_models) = cached_models else {
+        return validate_selection(selection, provider.known_models(), feature);
+    };
+    validate_selection(selection, Some(cached_models), feature).or_else(|err| {
+        validate_selection(selection, provider.known_models(), feature).map_err(|_| err)
+    })
+}
+
+fn validate_selection(
+    selection: &ModelSelection,
+    known_models: Option<&[&str]>,
+    feature: Feature,
+) -> Result<(), String> {
+    let name = &selection.name;
+    if name.is_empty() {
+        return Err(format!("No model is configured for {feature}"));
+    }
+    if let Some(known_models) = known_models {
+        if !known_models.contains(&name.as_str()) {
+            return Err(format!(
+                "Unknown model \"{name}\" for {feature}, available models are: {}",
+                known_models.join(", ")
+            ));
+        }
+    }
+
+    let parameters = &selection.parameters;
+    if let Some(temperature) = parameters.temperature {
+        if !(0.0..=MAX_TEMPERATURE).contains(&temperature) {
+            return Err(format!(
+                "Temperature must be between 0 and {MAX_TEMPERATURE}, got {temperature}"
+            ));
+        }
+    }
+    if parameters.max_tokens == Some(0) {
+        return Err("Max tokens must be a positive integer, got 0".to_owned());
+    }
+    if parameters.stop.len() > MAX_STOP_SEQUENCES {
+        return Err(format!(
+            "At most {MAX_STOP_SEQUENCES} stop sequences are allowed, got {}",
+            parameters.stop.len()
+        ));
+    }
+    if parameters.stop.iter().any(String::is_empty) {
+        return Err("Stop sequences must not be empty".to_owned());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_selection(name: &str) -> ModelSelection {
+        ModelSelection {
+            name: name.to_owned(),
+            parameters: GenerationParameters::default(),
+        }
+    }
+
+    #[test]
+    fn test_validate_model() {
+        let known_models = Provider::Cursor.known_models();
+        assert!(validate_selection(&make_selection("gpt-4"), known_models, Feature::Chat).is_ok());
+        assert_eq!(
+            validate_selection(&make_selection("gpt-5"), known_models, Feature::Edit),
+            Err(
+                "Unknown model \"gpt-5\" for edit, available models are: gpt-3.5-turbo, gpt-4"
+                    .to_owned()
+            )
+        );
+        assert!(validate_selection(&make_selection("codellama"), None, Feature::Chat).is_ok());
+        assert!(validate_selection(&make_selection(""), None, Feature::Chat).is_err());
+    }
+
+    #[test]
+    fn test_validate_model_with_cache() {
+        let validate = |name, provider, cached_models: &[&str]| {
+            validate_model(
+                &make_selection(name),
+                provider,
+                Some(cached_models),
+                Feature::Chat,
+            )
+        };
+        assert!(validate("gpt-4-32k", Provider::Cursor, &["gpt-4-32k"]).is_ok());
+        // A stale cache falls back to the built-in models.
+        assert!(validate("gpt-4", Provider::Cursor, &["gpt-3.5-turbo"]).is_ok());
+        assert_eq!(
+            validate("gpt-5", Provider::Cursor, &["gpt-3.5-turbo"]),
+            Err("Unknown model \"gpt-5\" for chat, available models are: gpt-3.5-turbo".to_owned())
+        );
+        assert!(validate("mistral", Provider::Local, &["codellama"]).is_ok());
+    }
+
+    #[test]
+    fn test_provider_for_feature() {
+        assert_eq!(Provider::Local.for_feature(Feature::Chat), Provider::Local);
+        assert_eq!(
+            Provider::Local.for_feature(Feature::Project),
+            Provider::Cursor
+        );
+        assert_eq!(
+            Provider::OpenAi.for_feature(Feature::Project),
+            Provider::Cursor
+        );
+    }
+
+    #[test]
+    fn test_validate_parameters() {
+        let validate = |parameters| {
+            let selection = ModelSelection {
+                parameters,
+                ..make_selection("gpt-4")
+            };
+            validate_selection(&selection, None, Feature::Generate)
+        };
+        assert!(validate(GenerationParameters {
+            temperature: Some(0.2),
+            max_tokens: Some(1024),
+            stop: vec!["\n\n".to_owned()],
+        })
+        .is_ok());
+        assert!(validate(GenerationParameters {
+            temperature: Some(2.5),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate(GenerationParameters {
+            max_tokens: Some(0),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate(GenerationParameters {
+            stop: vec!["a".to_owned(); 5],
+            ..Default::default()
+        })
+        .is_err());
+        assert!(validate(GenerationParameters {
+            stop: vec![String::new()],
+            ..Default::default()
+        })
+        .is_err());
+    }
 }
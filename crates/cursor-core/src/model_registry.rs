@@ -0,0 +1,196 @@
+//! Models available from the active backend.
+//!
+//! The list is fetched from the provider and cached in the global storage
+//! for a day, keyed by the backend and the account, so that the model picker
+//! and the validation of the configuration reflect what can really be used.
+
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    auth::active_account_name,
+    context::get_extension_context,
+    model_configuration::{ModelConfiguration, Provider, CURSOR_MODELS},
+    services::provider::provider_for,
+};
+
+const MODELS_KEY: &str = "models";
+const CACHE_TTL_HOURS: i64 = 24;
+/// Assumed for the models whose context window is unknown.
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 4096;
+
+/// Context windows of well-known models, matched by name prefix in order,
+/// so more specific names come first.
+const CONTEXT_WINDOWS: [(&str, u32); 14] = [
+    ("gpt-4o", 128000),
+    ("gpt-4-turbo", 128000),
+    ("gpt-4-1106", 128000),
+    ("gpt-4-0125", 128000),
+    ("gpt-4-32k", 32768),
+    ("gpt-4", 8192),
+    ("gpt-3.5-turbo-16k", 16385),
+    ("gpt-3.5-turbo", 4096),
+    ("codellama", 16384),
+    ("deepseek-coder", 16384),
+    ("llama3", 8192),
+    ("llama2", 4096),
+    ("mistral", 32768),
+    ("mixtral", 32768),
+];
+
+#[wasm_bindgen(getter_with_clone, js_name = ModelInfo)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub name: String,
+
+    /// The maximum number of tokens of the prompt and the reply together.
+    #[wasm_bindgen(js_name = contextWindow)]
+    #[serde(rename = "contextWindow")]
+    pub context_window: u32,
+}
+
+impl ModelInfo {
+    /// Creates the info, looking up the context window if the backend
+    /// doesn't report it.
+    pub fn new(name: String, context_window: Option<u32>) -> Self {
+        let context_window = context_window.unwrap_or_else(|| known_context_window(&name));
+        Self {
+            name,
+            context_window,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedModels {
+    backend: String,
+    fetched_at: i64,
+    models: Vec<ModelInfo>,
+}
+
+/// Identifies the backend the models are fetched from, along with the
+/// account for the Cursor server since the models depend on the plan.
+fn backend_key(configuration: &ModelConfiguration) -> String {
+    match configuration.provider() {
+        Provider::Cursor => format!(
+            "{:?}:{}",
+            Provider::Cursor,
+            active_account_name().unwrap_or_default()
+        ),
+        provider => format!(
+            "{:?}:{}",
+            provider,
+            configuration.base_url().unwrap_or_default()
+        ),
+    }
+}
+
+/// Drops the cached models, e.g. when the account changes.
+pub fn clear_cache() {
+    get_extension_context().storage().update(MODELS_KEY, None);
+}
+
+fn load_cache(configuration: &ModelConfiguration) -> Option<CachedModels> {
+    let data = get_extension_context().storage().get(MODELS_KEY)?;
+    serde_json::from_str::<CachedModels>(&data)
+        .ok()
+        .filter(|cache| cache.backend == backend_key(configuration))
+}
+
+/// Returns the cached models of the active backend, regardless of their age.
+pub fn cached_models(configuration: &ModelConfiguration) -> Option<Vec<ModelInfo>> {
+    load_cache(configuration).map(|cache| cache.models)
+}
+
+/// Returns the context window of the model, preferring what the backend
+/// reported.
+pub fn context_window(configuration: &ModelConfiguration, name: &str) -> u32 {
+    cached_models(configuration)
+        .and_then(|models| models.into_iter().find(|model| model.name == name))
+        .map(|model| model.context_window)
+        .unwrap_or_else(|| known_context_window(name))
+}
+
+fn known_context_window(name: &str) -> u32 {
+    // Strip the namespace, like `library/mistral`, tags like `codellama:13b`
+    // are matched by the prefix.
+    let name = name.rsplit('/').next().unwrap_or(name);
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, context_window)| *context_window)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+/// Returns the models of the active backend, fetching them unless the cache
+/// is fresh.
+///
+/// If fetching fails, the stale cache is returned, or the built-in list for
+/// the Cursor server.
+pub async fn available_models(force_refresh: bool) -> Result<Vec<ModelInfo>, JsValue> {
+    let configuration = get_extension_context().model_configuration();
+    let cache = load_cache(&configuration);
+    if let Some(cache) = &cache {
+        let age = Utc::now().timestamp() - cache.fetched_at;
+        if !force_refresh && age < Duration::hours(CACHE_TTL_HOURS).num_seconds() {
+            return Ok(cache.models.clone());
+        }
+    }
+
+    match provider_for(&configuration).list_models().await {
+        Ok(mut models) => {
+            models.sort_by(|a, b| a.name.cmp(&b.name));
+            let cache = CachedModels {
+                backend: backend_key(&configuration),
+                fetched_at: Utc::now().timestamp(),
+                models: models.clone(),
+            };
+            if let Ok(data) = serde_json::to_string(&cache) {
+                get_extension_context()
+                    .storage()
+                    .update(MODELS_KEY, Some(&data));
+            }
+            Ok(models)
+        }
+        Err(_) if cache.is_some() => Ok(cache.map(|cache| cache.models).unwrap_or_default()),
+        Err(_) if configuration.provider() == Provider::Cursor => Ok(CURSOR_MODELS
+            .into_iter()
+            .map(|name| ModelInfo::new(name.to_owned(), None))
+            .collect()),
+        Err(err) => Err(err),
+    }
+}
+
+#[wasm_bindgen(js_name = listModels)]
+pub async fn list_models(force_refresh: Option<bool>) -> Result<Vec<ModelInfo>, JsValue> {
+    available_models(force_refresh.unwrap_or(false)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_context_window() {
+        assert_eq!(known_context_window("gpt-4"), 8192);
+        assert_eq!(known_context_window("gpt-4-32k-0613"), 32768);
+        assert_eq!(known_context_window("gpt-4-turbo-preview"), 128000);
+        assert_eq!(known_context_window("gpt-3.5-turbo-16k"), 16385);
+        assert_eq!(known_context_window("codellama:13b"), 16384);
+        assert_eq!(known_context_window("library/mistral"), 32768);
+        assert_eq!(known_context_window("my-model"), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn test_model_info() {
+        assert_eq!(
+            ModelInfo::new("gpt-4".to_owned(), Some(1000)).context_window,
+            1000
+        );
+        assert_eq!(
+            ModelInfo::new("gpt-4".to_owned(), None).context_window,
+            8192
+        );
+    }
+}
@@ -16,9 +16,12 @@ use crate::{
         progress::Progress, progress_location::ProgressLocation, progress_options::ProgressOptions,
     },
     context::get_extension_context,
+    model_configuration::Feature,
     request::{make_request, stream::StreamResponseState, JsonSendable, INTERNAL_HOST},
 };
 
+use crate::services::stream::models::model_details::ModelDetails;
+
 use self::handler::ProjectHandler;
 
 const STEP_MESSAGE: &str = "cursor-step";
@@ -46,6 +49,11 @@ impl Task {
 #[wasm_bindgen(js_name = generateProject)]
 pub async fn generate_project(prompt: &str, handler: ProjectHandler) -> Result<JsValue, JsValue> {
     let prompt = prompt.to_owned();
+    let configuration = get_extension_context().model_configuration();
+    let model_details = ModelDetails::new(
+        configuration.select_model_or_report(Feature::Project)?,
+        configuration.api_key(),
+    );
     Ok(get_extension_context()
         .with_progress(
             ProgressOptions {
@@ -67,7 +75,10 @@ pub async fn generate_project(prompt: &str, handler: ProjectHandler) -> Result<J
                 let task = async move {
                     let mut state: StreamResponseState =
                         make_request(INTERNAL_HOST, "/gen_project", HttpMethod::Post)
-                            .set_json_body(&json!({ "description": prompt }))
+                            .set_json_body(&json!({
+                                "description": prompt,
+                                "modelDetails": model_details,
+                            }))
                             .send()
                             .await?
                             .into();
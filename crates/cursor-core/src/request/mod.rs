@@ -2,9 +2,38 @@ pub mod stream;
 
 use node_bridge::http_client::{HttpMethod, HttpRequest};
 use serde::Serialize;
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::context::get_extension_context;
 
 pub const API2_HOST: &str = "api2.cursor.sh";
 pub const INTERNAL_HOST: &str = "internal.cursor.sh";
+const DEFAULT_AUTH_HOST: &str = "cursor.us.auth0.com";
+
+/// Returns the host of the OAuth server, which can be configured for
+/// self-hosted setups.
+pub fn auth_host() -> String {
+    get_extension_context()
+        .model_configuration()
+        .auth_host()
+        .map(|host| {
+            host.trim()
+                .trim_start_matches("https://")
+                .trim_end_matches('/')
+                .to_owned()
+        })
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| DEFAULT_AUTH_HOST.to_owned())
+}
+
+/// Returns the message of an error thrown by JavaScript, which is not
+/// necessarily an `Error`.
+pub fn error_message(err: &JsValue) -> String {
+    err.dyn_ref::<js_sys::Error>()
+        .map(|err| String::from(err.message()))
+        .or_else(|| err.as_string())
+        .unwrap_or_else(|| format!("{err:?}"))
+}
 
 pub fn make_request(host: &str, path: &str, method: HttpMethod) -> HttpRequest {
     HttpRequest::new(&format!("https://{host}{path}"))
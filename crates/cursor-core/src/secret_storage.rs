@@ -0,0 +1,31 @@
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const ISECRET_STORAGE: &'static str = r#"
+interface ISecretStorage {
+    get(key: string): Thenable<string | undefined>;
+    store(key: string, value: string): Thenable<void>;
+    delete(key: string): Thenable<void>;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "ISecretStorage")]
+    pub type SecretStorage;
+
+    #[wasm_bindgen(method, structural, js_name = get)]
+    async fn get_raw(this: &SecretStorage, key: &str) -> JsValue;
+
+    #[wasm_bindgen(method, structural)]
+    pub async fn store(this: &SecretStorage, key: &str, value: &str);
+
+    #[wasm_bindgen(method, structural)]
+    pub async fn delete(this: &SecretStorage, key: &str);
+}
+
+impl SecretStorage {
+    pub async fn get(&self, key: &str) -> Option<String> {
+        self.get_raw(key).await.as_string()
+    }
+}
@@ -0,0 +1,106 @@
+//! Profile and usage quota of the signed-in account.
+//!
+//! The info is cached per account for a few minutes, since it's polled by
+//! the status bar and only changes as requests are made.
+
+pub mod models;
+
+use std::cell::RefCell;
+
+use chrono::{DateTime, Duration, Utc};
+use futures::join;
+use node_bridge::{http_client::HttpMethod, prelude::*};
+use serde::de::DeserializeOwned;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    auth::{auth_status, token::Token, valid_account_token},
+    request::{auth_host, error_message, make_request, API2_HOST},
+};
+
+use self::models::{AccountInfo, Profile, StripeProfile, Usage};
+
+const CACHE_TTL_SECS: i64 = 5 * 60;
+
+struct CachedAccountInfo {
+    fetched_at: DateTime<Utc>,
+    info: AccountInfo,
+}
+
+thread_local! {
+    static CACHE: RefCell<Option<CachedAccountInfo>> = const { RefCell::new(None) };
+}
+
+/// Returns the profile and usage of the account used in the current
+/// workspace, or `null` if not signed in.
+///
+/// The cached info is returned if it's fresh enough, unless `force_refresh`
+/// is set.
+#[wasm_bindgen(js_name = getAccountInfo)]
+pub async fn get_account_info(force_refresh: Option<bool>) -> Result<Option<AccountInfo>, JsValue> {
+    let (Some(account_name), Some(token)) =
+        (auth_status().await.account, valid_account_token().await)
+    else {
+        return Ok(None);
+    };
+
+    if !force_refresh.unwrap_or(false) {
+        let cached = CACHE.with(|cache| {
+            cache
+                .borrow()
+                .as_ref()
+                .filter(|cached| {
+                    cached.info.account == account_name
+                        && Utc::now() - cached.fetched_at < Duration::seconds(CACHE_TTL_SECS)
+                })
+                .map(|cached| cached.info.clone())
+        });
+        if cached.is_some() {
+            return Ok(cached);
+        }
+    }
+
+    let auth_host = auth_host();
+    let (profile, stripe_profile, usage) = join!(
+        get_json::<Profile>(&auth_host, "/userinfo", &token),
+        get_json::<StripeProfile>(API2_HOST, "/auth/full_stripe_profile", &token),
+        get_json::<Usage>(API2_HOST, "/auth/usage", &token),
+    );
+    if let (Err(err), Err(_)) = (&usage, &stripe_profile) {
+        // Nothing useful can be shown without the plan and the usage.
+        return Err(js_sys::Error::new(&format!("Failed to fetch account info: {err}")).into());
+    }
+
+    let info = AccountInfo::new(account_name, profile.ok(), stripe_profile.ok(), usage.ok());
+    CACHE.with(|cache| {
+        *cache.borrow_mut() = Some(CachedAccountInfo {
+            fetched_at: Utc::now(),
+            info: info.clone(),
+        })
+    });
+    Ok(Some(info))
+}
+
+/// Drops the cached info, so that the next call fetches it again.
+pub fn invalidate_account_info() {
+    CACHE.with(|cache| cache.borrow_mut().take());
+}
+
+async fn get_json<T>(host: &str, path: &str, token: &Token) -> Result<T, String>
+where
+    T: DeserializeOwned,
+{
+    let mut response = make_request(host, path, HttpMethod::Get)
+        .add_header("Authorization", &format!("Bearer {}", token.access_token))
+        .send()
+        .await
+        .map_err(|err| error_message(&err))?;
+    let status_code = response.status_code();
+    let data = response.text().await;
+    #[cfg(debug_assertions)]
+    console::log_str(&format!("{path} response: {data}"));
+    if status_code != 200 {
+        return Err(format!("server returned status code {status_code}"));
+    }
+    serde_json::from_str(&data).map_err(|err| err.to_string())
+}
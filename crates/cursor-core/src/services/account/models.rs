@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+/// The user profile returned by the `/userinfo` endpoint of the auth server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StripeProfile {
+    pub membership_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelUsage {
+    pub num_requests: u32,
+    /// `None` means the requests of this model are unlimited.
+    pub max_request_usage: Option<u32>,
+}
+
+/// The usage of the current billing period, keyed by model name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "RawUsage")]
+pub struct Usage {
+    pub start_of_month: Option<String>,
+    pub models: HashMap<String, ModelUsage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawUsage {
+    start_of_month: Option<String>,
+    #[serde(flatten)]
+    fields: HashMap<String, serde_json::Value>,
+}
+
+impl From<RawUsage> for Usage {
+    /// The models are mixed with other fields, only the values shaped like
+    /// the usage of a model are kept.
+    fn from(raw: RawUsage) -> Self {
+        Self {
+            start_of_month: raw.start_of_month,
+            models: raw
+                .fields
+                .into_iter()
+                .filter_map(|(name, value)| Some((name, serde_json::from_value(value).ok()?)))
+                .collect(),
+        }
+    }
+}
+
+impl Usage {
+    /// Returns the usage of fast requests, which are counted against the
+    /// quota of the GPT-4 model.
+    pub fn fast_requests(&self) -> Option<&ModelUsage> {
+        self.models.get(FAST_REQUESTS_MODEL)
+    }
+}
+
+const FAST_REQUESTS_MODEL: &str = "gpt-4";
+
+#[wasm_bindgen(getter_with_clone, js_name = AccountInfo)]
+#[derive(Debug, Clone)]
+pub struct AccountInfo {
+    /// The name of the local account this info belongs to.
+    pub account: String,
+
+    pub email: Option<String>,
+
+    pub name: Option<String>,
+
+    /// The subscription plan, e.g. `free` or `pro`.
+    pub plan: Option<String>,
+
+    #[wasm_bindgen(js_name = fastRequestsUsed)]
+    pub fast_requests_used: Option<u32>,
+
+    #[wasm_bindgen(js_name = fastRequestsLimit)]
+    pub fast_requests_limit: Option<u32>,
+
+    #[wasm_bindgen(js_name = fastRequestsRemaining)]
+    pub fast_requests_remaining: Option<u32>,
+
+    /// Whether the remaining fast requests are running low.
+    #[wasm_bindgen(js_name = quotaLow)]
+    pub quota_low: bool,
+
+    /// The start of the current billing period, as an ISO 8601 string.
+    #[wasm_bindgen(js_name = periodStart)]
+    pub period_start: Option<String>,
+}
+
+impl AccountInfo {
+    pub fn new(
+        account: String,
+        profile: Option<Profile>,
+        stripe_profile: Option<StripeProfile>,
+        usage: Option<Usage>,
+    ) -> Self {
+        let fast_requests = usage.as_ref().and_then(Usage::fast_requests);
+        let fast_requests_used = fast_requests.map(|u| u.num_requests);
+        let fast_requests_limit = fast_requests.and_then(|u| u.max_request_usage);
+        let (email, name) = profile.map(|p| (p.email, p.name)).unwrap_or_default();
+        Self {
+            account,
+            email,
+            name,
+            plan: stripe_profile.and_then(|p| p.membership_type),
+            fast_requests_remaining: fast_requests_limit
+                .map(|limit| limit.saturating_sub(fast_requests_used.unwrap_or_default())),
+            quota_low: is_quota_low(fast_requests_used, fast_requests_limit),
+            fast_requests_used,
+            fast_requests_limit,
+            period_start: usage.and_then(|u| u.start_of_month),
+        }
+    }
+}
+
+/// The quota is considered low when no more than a tenth of it is left.
+fn is_quota_low(used: Option<u32>, limit: Option<u32>) -> bool {
+    match (used, limit) {
+        (Some(used), Some(limit)) => limit.saturating_sub(used) * 10 <= limit,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_usage() {
+        let usage: Usage = serde_json::from_str(
+            r#"{
+                "gpt-4": {"numRequests": 42, "maxRequestUsage": 500},
+                "gpt-3.5-turbo": {"numRequests": 7, "maxRequestUsage": null},
+                "startOfMonth": "2023-05-01T00:00:00.000Z",
+                "hardLimit": true
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            usage.start_of_month.as_deref(),
+            Some("2023-05-01T00:00:00.000Z")
+        );
+        assert_eq!(usage.models.len(), 2);
+
+        let info = AccountInfo::new("default".to_owned(), None, None, Some(usage));
+        assert_eq!(info.fast_requests_used, Some(42));
+        assert_eq!(info.fast_requests_limit, Some(500));
+        assert_eq!(info.fast_requests_remaining, Some(458));
+        assert!(!info.quota_low);
+    }
+
+    #[test]
+    fn test_is_quota_low() {
+        assert!(!is_quota_low(Some(0), Some(500)));
+        assert!(!is_quota_low(Some(449), Some(500)));
+        assert!(is_quota_low(Some(450), Some(500)));
+        assert!(is_quota_low(Some(600), Some(500)));
+        assert!(!is_quota_low(Some(600), None));
+        assert!(!is_quota_low(None, None));
+    }
+}
@@ -0,0 +1,161 @@
+//! Fitting requests into the context window of the model.
+//!
+//! Tokens are counted with the `cl100k_base` encoding bundled in the binary.
+//! It's exact for the OpenAI models and a close enough estimate for others.
+
+use tiktoken_rs::CoreBPE;
+
+use crate::{model_configuration::ModelConfiguration, model_registry};
+
+use super::stream::models::model_details::ModelDetails;
+
+/// Reserved for the reply when the max tokens are not configured.
+const DEFAULT_REPLY_TOKENS: usize = 1024;
+/// Reserved for the instructions wrapped around the context by the prompt.
+const PROMPT_OVERHEAD_TOKENS: usize = 256;
+/// Each message of a conversation costs a few tokens for its role.
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+thread_local! {
+    static BPE: CoreBPE = tiktoken_rs::cl100k_base().expect("the bundled encoding is valid");
+}
+
+pub fn count_tokens(text: &str) -> usize {
+    BPE.with(|bpe| bpe.encode_ordinary(text).len())
+}
+
+pub fn count_message_tokens(text: &str) -> usize {
+    count_tokens(text) + MESSAGE_OVERHEAD_TOKENS
+}
+
+/// The number of tokens the prompt may take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextBudget {
+    pub prompt_tokens: usize,
+}
+
+impl ContextBudget {
+    pub fn new(context_window: usize, max_reply_tokens: Option<usize>) -> Self {
+        let reply_tokens =
+            max_reply_tokens.unwrap_or_else(|| DEFAULT_REPLY_TOKENS.min(context_window / 4));
+        Self {
+            prompt_tokens: context_window
+                .saturating_sub(reply_tokens)
+                .saturating_sub(PROMPT_OVERHEAD_TOKENS),
+        }
+    }
+
+    pub fn for_model(configuration: &ModelConfiguration, model: &ModelDetails) -> Self {
+        let context_window = model_registry::context_window(configuration, &model.name);
+        Self::new(
+            context_window as usize,
+            model.max_tokens.map(|max_tokens| max_tokens as usize),
+        )
+    }
+}
+
+/// Returns the inclusive range of lines to keep, which always covers the
+/// lines from `first` to `last`, and grows alternately upwards and downwards
+/// while the `costs` of the lines fit in `budget`.
+pub fn window_around(costs: &[usize], first: usize, last: usize, budget: usize) -> (usize, usize) {
+    if costs.is_empty() {
+        return (0, 0);
+    }
+    let last = last.min(costs.len() - 1);
+    let first = first.min(last);
+    let mut used = costs[first..=last].iter().sum::<usize>();
+    let (mut start, mut end) = (first, last);
+    let (mut can_grow_up, mut can_grow_down) = (true, true);
+    while can_grow_up || can_grow_down {
+        if can_grow_up {
+            match start.checked_sub(1) {
+                Some(line) if used + costs[line] <= budget => {
+                    used += costs[line];
+                    start = line;
+                }
+                _ => can_grow_up = false,
+            }
+        }
+        if can_grow_down {
+            match costs.get(end + 1) {
+                Some(cost) if used + cost <= budget => {
+                    used += cost;
+                    end += 1;
+                }
+                _ => can_grow_down = false,
+            }
+        }
+    }
+    (start, end)
+}
+
+/// Returns how many of the oldest messages to drop so that the rest fit in
+/// `budget`.
+///
+/// The messages are user and bot pairs, which are dropped together so that
+/// the rest starts with a message of the user. The last pair is always kept.
+pub fn messages_to_drop(costs: &[usize], budget: usize) -> usize {
+    let mut used = 0;
+    let kept = costs
+        .iter()
+        .rev()
+        .enumerate()
+        .take_while(|(index, cost)| {
+            used += *cost;
+            *index < 2 || used <= budget
+        })
+        .count();
+    let dropped = costs.len() - kept;
+    dropped + dropped % 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_tokens() {
+        assert_eq!(count_tokens(""), 0);
+        assert_eq!(count_tokens("hello world"), 2);
+    }
+
+    #[test]
+    fn test_context_budget() {
+        assert_eq!(
+            ContextBudget::new(8192, None).prompt_tokens,
+            8192 - 1024 - 256
+        );
+        assert_eq!(
+            ContextBudget::new(2048, None).prompt_tokens,
+            2048 - 512 - 256
+        );
+        assert_eq!(ContextBudget::new(4096, Some(4000)).prompt_tokens, 0);
+    }
+
+    #[test]
+    fn test_window_around() {
+        let costs = [1, 1, 1, 1, 1, 1, 1, 1];
+        assert_eq!(window_around(&costs, 3, 4, 4), (2, 5));
+        assert_eq!(window_around(&costs, 0, 0, 3), (0, 2));
+        assert_eq!(window_around(&costs, 7, 7, 3), (5, 7));
+        assert_eq!(window_around(&costs, 0, 7, 100), (0, 7));
+        // The selection is kept even if it doesn't fit.
+        assert_eq!(window_around(&costs, 2, 5, 1), (2, 5));
+        // A costly line stops the growth in its direction only.
+        assert_eq!(window_around(&[1, 10, 1, 1, 1], 2, 2, 3), (2, 4));
+        assert_eq!(window_around(&[], 3, 4, 10), (0, 0));
+    }
+
+    #[test]
+    fn test_messages_to_drop() {
+        assert_eq!(messages_to_drop(&[10, 10, 10, 10], 40), 0);
+        assert_eq!(messages_to_drop(&[10, 10, 10, 10], 20), 2);
+        // The budget cuts in the middle of a pair, the whole pair is dropped.
+        assert_eq!(messages_to_drop(&[10, 10, 10, 10], 35), 2);
+        assert_eq!(messages_to_drop(&[10, 10, 10, 10, 10, 10], 45), 2);
+        // The last pair is kept even if it doesn't fit.
+        assert_eq!(messages_to_drop(&[10, 10, 50, 1], 25), 2);
+        assert_eq!(messages_to_drop(&[10, 1], 5), 0);
+        assert_eq!(messages_to_drop(&[], 25), 0);
+    }
+}
@@ -1,5 +1,7 @@
-mod models;
+pub mod models;
+pub mod registry;
 mod session;
+mod store;
 
 use std::cell::RefCell;
 use std::future::IntoFuture;
@@ -10,25 +12,126 @@ use node_bridge::prelude::*;
 use wasm_bindgen::prelude::*;
 
 use crate::GenerateInput;
-use session::Session;
+use registry::{ChatSessionInfo, ChatTurnInfo, SessionRegistry, DEFAULT_SESSION_ID};
+use session::ChatAction;
 
-enum SharedSessionState {
-    Available(Option<Box<Session>>),
-    Occupied,
+thread_local! {
+    static SESSIONS: RefCell<Option<SessionRegistry>> = const { RefCell::new(None) };
 }
 
-thread_local! {
-    static SHARED_SESSION: RefCell<SharedSessionState> = const { RefCell::new(SharedSessionState::Available(None)) };
+/// Runs `f` with the sessions, restoring them from the storage on first use.
+fn with_sessions<R>(f: impl FnOnce(&mut SessionRegistry) -> R) -> R {
+    SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        let registry =
+            sessions.get_or_insert_with(|| SessionRegistry::restore(store::load_sessions()));
+        f(registry)
+    })
+}
+
+/// Like [`with_sessions`], and stores the sessions afterwards.
+fn update_sessions<R>(f: impl FnOnce(&mut SessionRegistry) -> R) -> R {
+    with_sessions(|registry| {
+        let result = f(registry);
+        store::save_sessions(registry.to_stored(store::load_sessions()));
+        result
+    })
+}
+
+/// Creates a chat session with its own conversation, the name defaults to
+/// "Chat N".
+#[wasm_bindgen(js_name = createChatSession)]
+pub fn create_chat_session(name: Option<String>) -> ChatSessionInfo {
+    update_sessions(|sessions| sessions.create(name))
+}
+
+/// Returns the chat sessions, the most recently updated first.
+#[wasm_bindgen(js_name = listChatSessions)]
+pub fn list_chat_sessions() -> Vec<ChatSessionInfo> {
+    with_sessions(|sessions| sessions.list())
+}
+
+#[wasm_bindgen(js_name = deleteChatSession)]
+pub fn delete_chat_session(session_id: &str) -> Result<(), JsValue> {
+    update_sessions(|sessions| sessions.delete(session_id))?;
+    Ok(())
+}
+
+/// Returns the turns of the chat session, used to restore the chat panel
+/// when switching sessions.
+#[wasm_bindgen(js_name = getChatTurns)]
+pub fn get_chat_turns(session_id: Option<String>) -> Result<Vec<ChatTurnInfo>, JsValue> {
+    let session_id = session_id.as_deref().unwrap_or(DEFAULT_SESSION_ID);
+    Ok(with_sessions(|sessions| sessions.turns(session_id))?)
+}
+
+/// Switches the chat session to the `alternative`-th alternative of the
+/// `index`-th turn, the conversation continues from there.
+#[wasm_bindgen(js_name = switchChatBranch)]
+pub fn switch_chat_branch(
+    session_id: Option<String>,
+    index: usize,
+    alternative: usize,
+) -> Result<(), JsValue> {
+    let session_id = session_id.as_deref().unwrap_or(DEFAULT_SESSION_ID);
+    update_sessions(|sessions| sessions.select_branch(session_id, index, alternative))?;
+    Ok(())
 }
 
+/// Clears the conversation of the chat session, or the default one if not
+/// specified.
 #[wasm_bindgen(js_name = resetChat)]
-pub fn reset_chat() {
-    SHARED_SESSION
-        .with(|shared_session| shared_session.replace(SharedSessionState::Available(None)));
+pub fn reset_chat(session_id: Option<String>) -> Result<(), JsValue> {
+    let session_id = session_id.as_deref().unwrap_or(DEFAULT_SESSION_ID);
+    update_sessions(|sessions| sessions.reset(session_id))?;
+    Ok(())
 }
 
+/// Clears the conversations of all the chat sessions.
+pub fn reset_all_chats() {
+    update_sessions(|sessions| sessions.reset_all());
+}
+
+/// Sends a message in the chat session, or the default one if not
+/// specified.
+///
+/// Each session replies to one message at a time, sending another message
+/// to a busy session fails.
 #[wasm_bindgen(js_name = chat)]
-pub async fn chat(input: &GenerateInput) -> Result<(), JsValue> {
+pub async fn chat(input: &GenerateInput, session_id: Option<String>) -> Result<(), JsValue> {
+    reply(input, session_id, ChatAction::Send).await
+}
+
+/// Replies to the last message of the chat session again, in a new branch.
+/// The prompt of the input is ignored.
+#[wasm_bindgen(js_name = regenerateLastReply)]
+pub async fn regenerate_last_reply(
+    input: &GenerateInput,
+    session_id: Option<String>,
+) -> Result<(), JsValue> {
+    reply(input, session_id, ChatAction::Regenerate).await
+}
+
+/// Replies to `text` in place of the message of the `index`-th turn of the
+/// chat session, in a new branch. The prompt of the input is ignored.
+#[wasm_bindgen(js_name = editMessage)]
+pub async fn edit_message(
+    input: &GenerateInput,
+    session_id: Option<String>,
+    index: usize,
+    text: String,
+) -> Result<(), JsValue> {
+    reply(input, session_id, ChatAction::Edit { index, text }).await
+}
+
+async fn reply(
+    input: &GenerateInput,
+    session_id: Option<String>,
+    action: ChatAction,
+) -> Result<(), JsValue> {
+    let session_id = session_id.unwrap_or_else(|| DEFAULT_SESSION_ID.to_owned());
+    let mut session = with_sessions(|sessions| sessions.check_out(&session_id))?;
+
     let defer_abort = Defer::new();
     let defer_abort_clone = defer_abort.clone();
     let abort_signal = input.abort_signal();
@@ -40,30 +143,19 @@ pub async fn chat(input: &GenerateInput) -> Result<(), JsValue> {
         .into_js_value(),
     );
 
-    // Get the shared session, create a new one if not existed.
-    let mut session = SHARED_SESSION.with(|shared_session| {
-        let session = match &mut *shared_session.borrow_mut() {
-            SharedSessionState::Occupied => {
-                // TODO: throw an error.
-                unreachable!("Cannot invoke chat concurrently")
-            }
-            SharedSessionState::Available(session) => session.take().unwrap_or_default(),
-        };
-        shared_session.replace(SharedSessionState::Occupied);
-        session
-    });
-
-    let fut = session.send_message(input);
+    let fut = session.send_message(input, action);
 
     let result = match select(defer_abort.into_future(), Box::pin(fut)).await {
-        Either::Left(_) => Ok(()),
-        Either::Right((res, _)) => res,
+        Either::Left(_) => None,
+        Either::Right((res, _)) => Some(res),
     };
-
-    // Put back the session to the global state, unlocking it for the next call.
-    SHARED_SESSION.with(|shared_session| {
-        shared_session.replace(SharedSessionState::Available(Some(session)))
+    let result = result.unwrap_or_else(|| {
+        session.cancel_pending();
+        Ok(())
     });
 
+    // Put back the session, unlocking it for the next call.
+    update_sessions(|sessions| sessions.check_in(&session_id, session));
+
     result
 }
@@ -1,6 +1,8 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize)]
+use crate::{services::workspace_path::relative_workspace_path, ContextItem};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CodeChunk {
     #[serde(rename = "relativeWorkspacePath")]
     pub relative_workspace_path: String,
@@ -10,3 +12,19 @@ pub struct CodeChunk {
 
     pub lines: Vec<String>,
 }
+
+impl CodeChunk {
+    /// Creates the chunk of an item attached to a request made from the
+    /// workspace folder `request_root`.
+    pub fn from_context_item(item: &ContextItem, request_root: Option<&str>) -> Self {
+        Self {
+            relative_workspace_path: relative_workspace_path(
+                &item.file_path(),
+                item.workspace_directory().as_deref(),
+                request_root,
+            ),
+            start_line: item.range().map(|range| range.start().line()).unwrap_or(0),
+            lines: item.contents().lines().map(str::to_owned).collect(),
+        }
+    }
+}
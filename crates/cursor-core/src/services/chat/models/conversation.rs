@@ -1,43 +1,41 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use crate::services::budget::{count_message_tokens, count_tokens};
 
 use super::code_chunk::CodeChunk;
 
 pub type Conversation = Vec<ConversationMessage>;
 
-#[derive(Debug, Clone)]
+/// Marks where the attached code is cut off.
+const ELISION_MARKER: &str = "... lines omitted ...";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MessageType {
+    #[serde(rename = "MESSAGE_TYPE_HUMAN")]
     User,
+    #[serde(rename = "MESSAGE_TYPE_AI")]
     Bot,
 }
 
-impl Serialize for MessageType {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str(&self.to_string())
-    }
-}
-
-impl ToString for MessageType {
-    fn to_string(&self) -> String {
-        match self {
-            MessageType::User => "MESSAGE_TYPE_HUMAN".to_owned(),
-            MessageType::Bot => "MESSAGE_TYPE_AI".to_owned(),
-        }
+impl std::fmt::Display for MessageType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MessageType::User => "MESSAGE_TYPE_HUMAN",
+            MessageType::Bot => "MESSAGE_TYPE_AI",
+        })
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ConversationMessage {
     #[serde(rename = "type")]
     pub message_type: MessageType,
 
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub text: String,
 
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub attached_code_chunks: Vec<CodeChunk>,
 }
 
@@ -53,4 +51,84 @@ impl ConversationMessage {
     pub fn empty_message(msg_type: MessageType) -> Self {
         Self::new(msg_type, "".to_owned())
     }
+
+    /// Estimates the tokens of the message along with the attached code.
+    pub fn token_count(&self) -> usize {
+        let code = self
+            .attached_code_chunks
+            .iter()
+            .flat_map(|chunk| &chunk.lines)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+        count_message_tokens(&self.text) + count_message_tokens(&code)
+    }
+
+    /// Cuts off the attached code so that the message takes no more than
+    /// `budget` tokens, the later lines are dropped first.
+    pub fn fit_code_chunks(&mut self, budget: usize) {
+        if self.token_count() <= budget {
+            return;
+        }
+        let marker_tokens = count_tokens(ELISION_MARKER) + 1;
+        let mut remaining = budget
+            .saturating_sub(count_message_tokens(&self.text) + count_message_tokens(""))
+            .saturating_sub(marker_tokens);
+        let mut kept_chunks = 0;
+        for chunk in &mut self.attached_code_chunks {
+            // Every line is joined with a newline.
+            let kept_lines = chunk
+                .lines
+                .iter()
+                .map(|line| count_tokens(line) + 1)
+                .take_while(|&cost| match remaining.checked_sub(cost) {
+                    Some(rest) => {
+                        remaining = rest;
+                        true
+                    }
+                    None => false,
+                })
+                .count();
+            if kept_lines < chunk.lines.len() {
+                chunk.lines.truncate(kept_lines);
+                chunk.lines.push(ELISION_MARKER.to_owned());
+                kept_chunks += 1;
+                break;
+            }
+            kept_chunks += 1;
+        }
+        self.attached_code_chunks.truncate(kept_chunks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_chunk(lines: usize) -> CodeChunk {
+        CodeChunk {
+            relative_workspace_path: "src/main.rs".to_owned(),
+            start_line: 0,
+            lines: (0..lines).map(|i| format!("let x{i} = {i};")).collect(),
+        }
+    }
+
+    #[test]
+    fn test_fit_code_chunks() {
+        let mut message = ConversationMessage::new(MessageType::User, "Explain".to_owned());
+        message.attached_code_chunks = vec![make_chunk(10), make_chunk(1000), make_chunk(10)];
+        let cost = message.token_count();
+
+        message.fit_code_chunks(cost);
+        assert_eq!(message.attached_code_chunks.len(), 3);
+
+        message.fit_code_chunks(200);
+        assert!(message.token_count() <= 200);
+        assert_eq!(message.attached_code_chunks.len(), 2);
+        assert_eq!(message.attached_code_chunks[0].lines.len(), 10);
+        let cut = &message.attached_code_chunks[1].lines;
+        assert!(cut.len() > 1 && cut.len() < 1000);
+        assert_eq!(cut.last().map(String::as_str), Some(ELISION_MARKER));
+        assert_eq!(message.text, "Explain");
+    }
 }
@@ -1,3 +1,5 @@
 pub mod code_chunk;
 pub mod conversation;
 pub mod request_body;
+pub mod tree;
+pub mod turn;
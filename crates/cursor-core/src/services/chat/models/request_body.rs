@@ -2,9 +2,13 @@ use serde::Serialize;
 use uuid::Uuid;
 
 use crate::{
-    context::get_extension_context,
-    services::stream::models::{
-        current_file::CurrentFile, explicit_context::ExplicitContext, model_details::ModelDetails,
+    services::{
+        budget::{messages_to_drop, ContextBudget},
+        stream::models::{
+            current_file::CurrentFile, explicit_context::ExplicitContext,
+            model_details::ModelDetails,
+        },
+        workspace_path::workspace_folder_name,
     },
     GenerateInput,
 };
@@ -20,6 +24,7 @@ pub struct RequestBody {
     pub current_file: CurrentFile,
     pub model_details: ModelDetails,
 
+    /// The name of the workspace folder, not its absolute path.
     #[serde(rename = "workspaceRootPath")]
     pub root_path: String,
 
@@ -31,36 +36,172 @@ pub struct RequestBody {
 }
 
 impl RequestBody {
-    pub fn new_with_input(input: &GenerateInput) -> Self {
+    pub fn new_with_input(input: &GenerateInput, model_details: ModelDetails) -> Self {
         let mut message = ConversationMessage::new(MessageType::User, input.prompt());
-        message.attached_code_chunks.push(CodeChunk {
-            relative_workspace_path: input.file_path(),
-            start_line: input.selection_range().start().line(),
-            lines: input
-                .document_text()
-                .lines()
-                .map(|s| s.to_string())
-                .collect(),
-        });
-        let context = get_extension_context();
-        let configuration = context.model_configuration();
+        // The whole document is already sent as the current file, only
+        // attach the selected lines.
+        let selection = input.selection_range();
+        if !selection.is_empty() {
+            let start_line = selection.start().line();
+            message.attached_code_chunks.push(CodeChunk {
+                relative_workspace_path: input.relative_file_path(),
+                start_line,
+                lines: input
+                    .document_text()
+                    .lines()
+                    .skip(start_line)
+                    .take(selection.end().line() - start_line + 1)
+                    .map(|s| s.to_string())
+                    .collect(),
+            });
+        }
+        let workspace_directory = input.workspace_directory();
+        message.attached_code_chunks.extend(
+            input
+                .context_items()
+                .unwrap_or_default()
+                .iter()
+                .map(|item| CodeChunk::from_context_item(item, workspace_directory.as_deref())),
+        );
         Self {
             current_file: CurrentFile {
                 content: input.document_text(),
                 language_id: input.language_id(),
-                relative_workspace_path: input.file_path(),
+                relative_workspace_path: input.relative_file_path(),
                 selection: input.selection_range().into(),
                 cursor: input.cursor().into(),
             },
+            model_details,
+            root_path: workspace_folder_name(workspace_directory.as_deref()),
+            context: ExplicitContext::default(),
+            request_id: Uuid::new_v4().to_string(),
+            conversation: vec![message],
+        }
+    }
+
+    /// Drops the oldest messages and trims the file context to fit in the
+    /// budget.
+    ///
+    /// The conversation is made of the message and reply pairs of the turns,
+    /// the last of which is the pending turn. It takes up to half of the
+    /// budget, the oldest turns are dropped whole and the pending one is
+    /// always kept, with its attached code cut off to fit in that half.
+    pub fn fit_to_budget(&mut self, budget: ContextBudget) {
+        let budget = ContextBudget {
+            prompt_tokens: budget
+                .prompt_tokens
+                .saturating_sub(self.context.token_count()),
+        };
+        if let Some(message) = self
+            .conversation
+            .iter_mut()
+            .rev()
+            .find(|message| matches!(message.message_type, MessageType::User))
+        {
+            message.fit_code_chunks(budget.prompt_tokens / 2);
+        }
+        let costs = self
+            .conversation
+            .iter()
+            .map(ConversationMessage::token_count)
+            .collect::<Vec<_>>();
+        let dropped = messages_to_drop(&costs, budget.prompt_tokens / 2);
+        self.conversation.drain(..dropped);
+
+        let used = costs[dropped..].iter().sum::<usize>();
+        self.current_file
+            .fit_to_budget(budget.prompt_tokens.saturating_sub(used));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::{
+        chat::models::turn::{ChatTurn, TurnStatus},
+        stream::models::current_file::{Position, Selection},
+    };
+
+    use super::*;
+
+    fn make_request(turns: &[ChatTurn]) -> RequestBody {
+        let position = || Position { line: 0, column: 0 };
+        RequestBody {
+            current_file: CurrentFile {
+                content: String::new(),
+                language_id: "rust".to_owned(),
+                relative_workspace_path: "src/main.rs".to_owned(),
+                selection: Selection {
+                    start: position(),
+                    end: position(),
+                },
+                cursor: position(),
+            },
             model_details: ModelDetails {
-                name: configuration.model_name(),
+                name: "gpt-4".to_owned(),
                 ghost_mode: true,
-                api_key: configuration.api_key(),
+                api_key: None,
+                temperature: None,
+                max_tokens: None,
+                stop: vec![],
             },
-            root_path: input.workspace_directory().unwrap_or_default(),
-            context: ExplicitContext {},
-            request_id: Uuid::new_v4().to_string(),
-            conversation: vec![message],
+            root_path: String::new(),
+            context: ExplicitContext::default(),
+            request_id: String::new(),
+            conversation: turns.iter().flat_map(ChatTurn::to_messages).collect(),
         }
     }
+
+    fn make_turn(text: &str, reply: &str) -> ChatTurn {
+        let message = ConversationMessage::new(MessageType::User, text.to_owned());
+        let mut turn = ChatTurn::new(message, "gpt-4".to_owned());
+        turn.reply = reply.to_owned();
+        turn.finish(TurnStatus::Complete);
+        turn
+    }
+
+    #[test]
+    fn test_fit_to_budget() {
+        let words = |word: &str| vec![word; 50].join(" ");
+        // A message of the user with only attached code.
+        let mut pending = ChatTurn::new(
+            ConversationMessage::new(MessageType::User, String::new()),
+            "gpt-4".to_owned(),
+        );
+        pending.message.attached_code_chunks.push(CodeChunk {
+            relative_workspace_path: "src/main.rs".to_owned(),
+            start_line: 0,
+            lines: vec!["fn main() {}".to_owned()],
+        });
+        let turns = [
+            make_turn(&words("first"), &words("one")),
+            make_turn(&words("second"), &words("two")),
+            pending,
+        ];
+        let mut request = make_request(&turns);
+        let costs = request
+            .conversation
+            .iter()
+            .map(ConversationMessage::token_count)
+            .collect::<Vec<_>>();
+
+        // The budget ends in the middle of the second turn.
+        let budget = costs[3..].iter().sum::<usize>() + costs[2] / 2;
+        request.fit_to_budget(ContextBudget {
+            prompt_tokens: budget * 2,
+        });
+        let types = request
+            .conversation
+            .iter()
+            .map(|message| message.message_type.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(types, ["MESSAGE_TYPE_HUMAN", "MESSAGE_TYPE_AI"]);
+        assert_eq!(request.conversation[0].attached_code_chunks.len(), 1);
+        assert!(request.conversation[1].text.is_empty());
+
+        // The pending turn is kept even without any budget.
+        let mut request = make_request(&turns);
+        request.fit_to_budget(ContextBudget { prompt_tokens: 0 });
+        assert_eq!(request.conversation.len(), 2);
+        assert_eq!(request.conversation[0].attached_code_chunks.len(), 1);
+    }
 }
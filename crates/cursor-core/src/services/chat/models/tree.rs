@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+
+use super::turn::ChatTurn;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Node {
+    turn: ChatTurn,
+    parent: Option<usize>,
+}
+
+/// The turns of a conversation as a tree, each edited message or
+/// regenerated reply starts a branch next to the turn it replaces.
+///
+/// The conversation is the path from the root to the selected leaf.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "RawTree")]
+pub struct ConversationTree {
+    /// In the order they were added, so the siblings are sorted by age, and
+    /// each parent comes before its children.
+    nodes: Vec<Node>,
+    /// The last node of the selected path.
+    current: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct RawTree {
+    nodes: Vec<Node>,
+    current: Option<usize>,
+}
+
+impl TryFrom<RawTree> for ConversationTree {
+    type Error = String;
+
+    /// Checks the indices of the stored tree, which may be corrupted.
+    fn try_from(raw: RawTree) -> Result<Self, Self::Error> {
+        for (index, node) in raw.nodes.iter().enumerate() {
+            if node.parent.is_some_and(|parent| parent >= index) {
+                return Err(format!("The parent of node {index} does not precede it"));
+            }
+        }
+        if raw
+            .current
+            .is_some_and(|current| current >= raw.nodes.len())
+        {
+            return Err("The selected node does not exist".to_owned());
+        }
+        Ok(Self {
+            nodes: raw.nodes,
+            current: raw.current,
+        })
+    }
+}
+
+impl ConversationTree {
+    /// Creates a tree with a single branch.
+    pub fn from_turns(turns: Vec<ChatTurn>) -> Self {
+        let mut tree = Self::default();
+        for turn in turns {
+            tree.push(tree.current, turn);
+        }
+        tree
+    }
+
+    /// Returns the nodes of the selected path, from the root.
+    fn path(&self) -> Vec<usize> {
+        let mut path = vec![];
+        let mut node = self.current;
+        while let Some(index) = node {
+            path.push(index);
+            node = self.nodes[index].parent;
+        }
+        path.reverse();
+        path
+    }
+
+    fn siblings(&self, node: usize) -> Vec<usize> {
+        let parent = self.nodes[node].parent;
+        (0..self.nodes.len())
+            .filter(|&index| self.nodes[index].parent == parent)
+            .collect()
+    }
+
+    /// Returns the turns of the selected path, from the first.
+    pub fn turns(&self) -> Vec<&ChatTurn> {
+        self.path()
+            .into_iter()
+            .map(|index| &self.nodes[index].turn)
+            .collect()
+    }
+
+    /// Returns the turns of the selected path along with the position of
+    /// each among its alternatives and the number of them.
+    pub fn turns_with_alternatives(&self) -> Vec<(&ChatTurn, usize, usize)> {
+        self.path()
+            .into_iter()
+            .map(|index| {
+                let siblings = self.siblings(index);
+                let position = siblings.iter().position(|&i| i == index).unwrap();
+                (&self.nodes[index].turn, position, siblings.len())
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.path().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.is_none()
+    }
+
+    /// Returns the last turn of the selected path.
+    pub fn last_mut(&mut self) -> Option<&mut ChatTurn> {
+        self.current.map(|index| &mut self.nodes[index].turn)
+    }
+
+    /// Returns the node to branch from to replace the turn at `index` of the
+    /// selected path, `None` for the root.
+    ///
+    /// The index past the last turn continues the selected path.
+    pub fn parent_at(&self, index: usize) -> Result<Option<usize>, String> {
+        let path = self.path();
+        match path.get(index) {
+            Some(&node) => Ok(self.nodes[node].parent),
+            None if index == path.len() => Ok(self.current),
+            None => Err(format!("No message at index {index}")),
+        }
+    }
+
+    pub fn turn_at(&self, index: usize) -> Option<&ChatTurn> {
+        self.path().get(index).map(|&node| &self.nodes[node].turn)
+    }
+
+    /// Adds the turn under `parent` and selects it.
+    pub fn push(&mut self, parent: Option<usize>, turn: ChatTurn) {
+        self.nodes.push(Node { turn, parent });
+        self.current = Some(self.nodes.len() - 1);
+    }
+
+    /// Selects the `alternative`-th alternative of the turn at `index` of the
+    /// selected path, following its latest branch down to a leaf.
+    pub fn select(&mut self, index: usize, alternative: usize) -> Result<(), String> {
+        let path = self.path();
+        let node = *path
+            .get(index)
+            .ok_or_else(|| format!("No message at index {index}"))?;
+        let mut node = *self
+            .siblings(node)
+            .get(alternative)
+            .ok_or_else(|| format!("No alternative {alternative} of message {index}"))?;
+        while let Some(child) = (0..self.nodes.len())
+            .rev()
+            .find(|&i| self.nodes[i].parent == Some(node))
+        {
+            node = child;
+        }
+        self.current = Some(node);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::chat::models::conversation::{ConversationMessage, MessageType};
+
+    use super::*;
+
+    fn turn(text: &str) -> ChatTurn {
+        ChatTurn::new(
+            ConversationMessage::new(MessageType::User, text.to_owned()),
+            "gpt-4".to_owned(),
+        )
+    }
+
+    fn texts(tree: &ConversationTree) -> Vec<&str> {
+        tree.turns()
+            .into_iter()
+            .map(|turn| turn.message.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_branching() {
+        let mut tree = ConversationTree::from_turns(vec![turn("a"), turn("b"), turn("c")]);
+        assert_eq!(texts(&tree), ["a", "b", "c"]);
+
+        // Edit the second message.
+        let parent = tree.parent_at(1).unwrap();
+        tree.push(parent, turn("b2"));
+        assert_eq!(texts(&tree), ["a", "b2"]);
+        let alternatives = tree
+            .turns_with_alternatives()
+            .into_iter()
+            .map(|(_, position, count)| (position, count))
+            .collect::<Vec<_>>();
+        assert_eq!(alternatives, [(0, 1), (1, 2)]);
+
+        // Back to the first branch, down to its leaf.
+        tree.select(1, 0).unwrap();
+        assert_eq!(texts(&tree), ["a", "b", "c"]);
+
+        tree.select(1, 1).unwrap();
+        tree.push(tree.parent_at(2).unwrap(), turn("d"));
+        assert_eq!(texts(&tree), ["a", "b2", "d"]);
+
+        assert!(tree.select(1, 2).is_err());
+        assert!(tree.parent_at(4).is_err());
+    }
+
+    #[test]
+    fn test_read_corrupted_tree() {
+        let tree = ConversationTree::from_turns(vec![turn("a"), turn("b")]);
+        let data = serde_json::to_value(&tree).unwrap();
+        let restored = serde_json::from_value::<ConversationTree>(data.clone()).unwrap();
+        assert_eq!(texts(&restored), ["a", "b"]);
+
+        let corrupt = |pointer: &str, value| {
+            let mut data = data.clone();
+            *data.pointer_mut(pointer).unwrap() = serde_json::json!(value);
+            serde_json::from_value::<ConversationTree>(data)
+        };
+        assert!(corrupt("/current", 2).is_err());
+        assert!(corrupt("/nodes/1/parent", 5).is_err());
+        // A cycle.
+        assert!(corrupt("/nodes/0/parent", 1).is_err());
+        assert!(corrupt("/nodes/1/parent", 1).is_err());
+    }
+
+    #[test]
+    fn test_edit_first_message() {
+        let mut tree = ConversationTree::from_turns(vec![turn("a"), turn("b")]);
+        tree.push(tree.parent_at(0).unwrap(), turn("a2"));
+        assert_eq!(texts(&tree), ["a2"]);
+        tree.select(0, 0).unwrap();
+        assert_eq!(texts(&tree), ["a", "b"]);
+    }
+}
@@ -0,0 +1,138 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::conversation::{ConversationMessage, MessageType};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum TurnStatus {
+    /// The reply is being generated.
+    Pending,
+    Complete,
+    Cancelled,
+    Errored {
+        message: String,
+    },
+}
+
+impl std::fmt::Display for TurnStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TurnStatus::Pending => "pending",
+            TurnStatus::Complete => "complete",
+            TurnStatus::Cancelled => "cancelled",
+            TurnStatus::Errored { .. } => "errored",
+        })
+    }
+}
+
+/// A message of the user and the reply to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatTurn {
+    /// The message of the user, along with the attached code.
+    pub message: ConversationMessage,
+
+    pub reply: String,
+
+    /// The prompt the server filled the conversation into, only reported by
+    /// the Cursor server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filled_prompt: Option<String>,
+
+    /// The model that replied.
+    pub model: String,
+
+    /// Milliseconds since the Unix epoch.
+    pub started_at: i64,
+
+    /// Milliseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+
+    pub status: TurnStatus,
+}
+
+impl ChatTurn {
+    pub fn new(message: ConversationMessage, model: String) -> Self {
+        Self {
+            message,
+            reply: String::new(),
+            filled_prompt: None,
+            model,
+            started_at: Utc::now().timestamp_millis(),
+            finished_at: None,
+            status: TurnStatus::Pending,
+        }
+    }
+
+    pub fn finish(&mut self, status: TurnStatus) {
+        self.status = status;
+        self.finished_at = Some(Utc::now().timestamp_millis());
+    }
+
+    /// Returns how long the reply took, in milliseconds.
+    pub fn duration(&self) -> Option<i64> {
+        self.finished_at
+            .map(|finished_at| finished_at - self.started_at)
+    }
+
+    /// Returns the messages of the turn as sent to the backend.
+    ///
+    /// The reply of a pending turn is the empty placeholder of the message
+    /// being generated. Cancelled and errored turns are sent with what was
+    /// replied so far, or left out if nothing was.
+    pub fn to_messages(&self) -> Vec<ConversationMessage> {
+        if self.status != TurnStatus::Pending && self.reply.is_empty() {
+            return vec![];
+        }
+        vec![
+            self.message.clone(),
+            ConversationMessage::new(MessageType::Bot, self.reply.clone()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_serialization() {
+        let mut turn = ChatTurn::new(
+            ConversationMessage::new(MessageType::User, "hi".to_owned()),
+            "gpt-4".to_owned(),
+        );
+        turn.finish(TurnStatus::Errored {
+            message: "oops".to_owned(),
+        });
+        let data = serde_json::to_value(&turn).unwrap();
+        assert_eq!(
+            data["status"],
+            serde_json::json!({ "kind": "errored", "message": "oops" })
+        );
+        assert!(turn.duration().unwrap() >= 0);
+
+        let restored = serde_json::from_value::<ChatTurn>(data).unwrap();
+        assert_eq!(restored.status, turn.status);
+    }
+
+    #[test]
+    fn test_to_messages() {
+        let mut turn = ChatTurn::new(
+            ConversationMessage::new(MessageType::User, "hi".to_owned()),
+            "gpt-4".to_owned(),
+        );
+        let messages = turn.to_messages();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[1].text.is_empty());
+
+        turn.finish(TurnStatus::Cancelled);
+        assert!(turn.to_messages().is_empty());
+
+        turn.reply = "Hel".to_owned();
+        let messages = turn.to_messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].text, "Hel");
+    }
+}
@@ -0,0 +1,438 @@
+//! Chat sessions keyed by ID, each with its own conversation.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeZone, Utc};
+use uuid::Uuid;
+use wasm_bindgen::prelude::*;
+
+use super::{
+    models::turn::{ChatTurn, TurnStatus},
+    session::Session,
+    store::StoredSession,
+};
+
+/// The session used when no session ID is specified.
+pub const DEFAULT_SESSION_ID: &str = "default";
+const DEFAULT_SESSION_NAME: &str = "Default";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatError {
+    /// The session is replying to another message.
+    Busy(String),
+    NotFound(String),
+    /// The branch to switch to doesn't exist.
+    NoBranch(String),
+}
+
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatError::Busy(id) => write!(
+                f,
+                "Chat session \"{id}\" is busy replying to another message"
+            ),
+            ChatError::NotFound(id) => write!(f, "Chat session \"{id}\" does not exist"),
+            ChatError::NoBranch(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl From<ChatError> for JsValue {
+    fn from(err: ChatError) -> Self {
+        JsError::new(&err.to_string()).into()
+    }
+}
+
+#[wasm_bindgen(getter_with_clone, js_name = ChatSessionInfo)]
+#[derive(Debug, Clone)]
+pub struct ChatSessionInfo {
+    pub id: String,
+
+    pub name: String,
+
+    /// Milliseconds since the Unix epoch.
+    #[wasm_bindgen(js_name = createdAt)]
+    pub created_at: f64,
+
+    /// Milliseconds since the Unix epoch.
+    #[wasm_bindgen(js_name = updatedAt)]
+    pub updated_at: f64,
+
+    #[wasm_bindgen(js_name = turnCount)]
+    pub turn_count: usize,
+
+    /// The model that replied to the last message.
+    pub model: Option<String>,
+
+    pub busy: bool,
+}
+
+#[wasm_bindgen(getter_with_clone, js_name = ChatTurnInfo)]
+#[derive(Debug, Clone)]
+pub struct ChatTurnInfo {
+    pub prompt: String,
+
+    pub reply: String,
+
+    /// The prompt the server filled the conversation into.
+    #[wasm_bindgen(js_name = filledPrompt)]
+    pub filled_prompt: Option<String>,
+
+    pub model: String,
+
+    /// Milliseconds since the Unix epoch.
+    #[wasm_bindgen(js_name = startedAt)]
+    pub started_at: f64,
+
+    #[wasm_bindgen(js_name = durationMs)]
+    pub duration_ms: Option<f64>,
+
+    /// One of `pending`, `complete`, `cancelled` and `errored`.
+    pub status: String,
+
+    /// The reason of the failure if errored.
+    pub error: Option<String>,
+
+    /// The position of the turn among the alternatives, which are the
+    /// edited messages and regenerated replies in place of it.
+    pub alternative: usize,
+
+    #[wasm_bindgen(js_name = alternativeCount)]
+    pub alternative_count: usize,
+}
+
+impl ChatTurnInfo {
+    fn new(turn: &ChatTurn, alternative: usize, alternative_count: usize) -> Self {
+        Self {
+            prompt: turn.message.text.clone(),
+            reply: turn.reply.clone(),
+            filled_prompt: turn.filled_prompt.clone(),
+            model: turn.model.clone(),
+            started_at: turn.started_at as f64,
+            duration_ms: turn.duration().map(|duration| duration as f64),
+            status: turn.status.to_string(),
+            error: match &turn.status {
+                TurnStatus::Errored { message } => Some(message.clone()),
+                _ => None,
+            },
+            alternative,
+            alternative_count,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    /// `None` while the session is checked out to reply to a message.
+    session: Option<Box<Session>>,
+    /// Set when the session is reset while busy, it's applied on check in.
+    reset_pending: bool,
+}
+
+impl Entry {
+    fn new(name: String) -> Self {
+        let now = Utc::now();
+        Self {
+            name,
+            created_at: now,
+            updated_at: now,
+            session: Some(Box::default()),
+            reset_pending: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    entries: HashMap<String, Entry>,
+}
+
+impl SessionRegistry {
+    pub fn restore(sessions: Vec<StoredSession>) -> Self {
+        let timestamp = |millis| {
+            Utc.timestamp_millis_opt(millis)
+                .single()
+                .unwrap_or_default()
+        };
+        let entries = sessions
+            .into_iter()
+            .map(|stored| {
+                let entry = Entry {
+                    name: stored.name,
+                    created_at: timestamp(stored.created_at),
+                    updated_at: timestamp(stored.updated_at),
+                    session: Some(Box::new(Session::restore(stored.tree))),
+                    reset_pending: false,
+                };
+                (stored.id, entry)
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Returns the sessions to store, the busy ones are taken from
+    /// `previous` as they were before they started replying.
+    pub fn to_stored(&self, previous: Vec<StoredSession>) -> Vec<StoredSession> {
+        let mut previous = previous
+            .into_iter()
+            .map(|stored| (stored.id.clone(), stored))
+            .collect::<HashMap<_, _>>();
+        self.entries
+            .iter()
+            .filter_map(|(id, entry)| {
+                let Some(session) = &entry.session else {
+                    return previous.remove(id);
+                };
+                Some(StoredSession {
+                    id: id.clone(),
+                    name: entry.name.clone(),
+                    created_at: entry.created_at.timestamp_millis(),
+                    updated_at: entry.updated_at.timestamp_millis(),
+                    tree: session.tree().clone(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn create(&mut self, name: Option<String>) -> ChatSessionInfo {
+        let id = Uuid::new_v4().to_string();
+        let name = name
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| self.unused_name());
+        self.entries.insert(id.clone(), Entry::new(name));
+        self.info(&id).expect("the session was just inserted")
+    }
+
+    /// Returns the first of "Chat 1", "Chat 2", ... that no session is named.
+    fn unused_name(&self) -> String {
+        (1..)
+            .map(|n| format!("Chat {n}"))
+            .find(|name| self.entries.values().all(|entry| &entry.name != name))
+            .expect("the names are endless")
+    }
+
+    /// Takes the session out to reply to a message, it must be put back with
+    /// [`SessionRegistry::check_in`].
+    ///
+    /// The default session is created on first use.
+    pub fn check_out(&mut self, id: &str) -> Result<Box<Session>, ChatError> {
+        if id == DEFAULT_SESSION_ID && !self.entries.contains_key(id) {
+            self.entries
+                .insert(id.to_owned(), Entry::new(DEFAULT_SESSION_NAME.to_owned()));
+        }
+        let entry = self.entry_mut(id)?;
+        entry
+            .session
+            .take()
+            .ok_or_else(|| ChatError::Busy(id.to_owned()))
+    }
+
+    pub fn check_in(&mut self, id: &str, session: Box<Session>) {
+        if let Ok(entry) = self.entry_mut(id) {
+            entry.session = Some(if entry.reset_pending {
+                Box::default()
+            } else {
+                session
+            });
+            entry.reset_pending = false;
+            entry.updated_at = Utc::now();
+        }
+    }
+
+    pub fn delete(&mut self, id: &str) -> Result<(), ChatError> {
+        if self.entry_mut(id)?.session.is_none() {
+            return Err(ChatError::Busy(id.to_owned()));
+        }
+        self.entries.remove(id);
+        Ok(())
+    }
+
+    /// Clears the conversation of the session.
+    pub fn reset(&mut self, id: &str) -> Result<(), ChatError> {
+        let entry = match self.entry_mut(id) {
+            Err(_) if id == DEFAULT_SESSION_ID => return Ok(()),
+            entry => entry?,
+        };
+        if entry.session.is_none() {
+            return Err(ChatError::Busy(id.to_owned()));
+        }
+        entry.session = Some(Box::default());
+        entry.updated_at = Utc::now();
+        Ok(())
+    }
+
+    /// Clears the conversations of all the sessions, the busy ones are
+    /// cleared when they finish replying.
+    pub fn reset_all(&mut self) {
+        for entry in self.entries.values_mut() {
+            match entry.session {
+                Some(_) => entry.session = Some(Box::default()),
+                None => entry.reset_pending = true,
+            }
+        }
+    }
+
+    /// Returns the sessions, the most recently updated first.
+    pub fn list(&self) -> Vec<ChatSessionInfo> {
+        let mut sessions = self
+            .entries
+            .keys()
+            .filter_map(|id| self.info(id))
+            .collect::<Vec<_>>();
+        sessions.sort_by(|a, b| b.updated_at.total_cmp(&a.updated_at));
+        sessions
+    }
+
+    /// Returns the turns of the session, none for the default session before
+    /// it's first used.
+    pub fn turns(&self, id: &str) -> Result<Vec<ChatTurnInfo>, ChatError> {
+        let entry = match self.entries.get(id) {
+            Some(entry) => entry,
+            None if id == DEFAULT_SESSION_ID => return Ok(vec![]),
+            None => return Err(ChatError::NotFound(id.to_owned())),
+        };
+        let session = entry
+            .session
+            .as_ref()
+            .ok_or_else(|| ChatError::Busy(id.to_owned()))?;
+        Ok(session
+            .tree()
+            .turns_with_alternatives()
+            .into_iter()
+            .map(|(turn, alternative, count)| ChatTurnInfo::new(turn, alternative, count))
+            .collect())
+    }
+
+    /// Switches the session to another alternative of the turn at `index`.
+    pub fn select_branch(
+        &mut self,
+        id: &str,
+        index: usize,
+        alternative: usize,
+    ) -> Result<(), ChatError> {
+        let entry = self.entry_mut(id)?;
+        let session = entry
+            .session
+            .as_mut()
+            .ok_or_else(|| ChatError::Busy(id.to_owned()))?;
+        session
+            .select_branch(index, alternative)
+            .map_err(ChatError::NoBranch)?;
+        entry.updated_at = Utc::now();
+        Ok(())
+    }
+
+    fn info(&self, id: &str) -> Option<ChatSessionInfo> {
+        let entry = self.entries.get(id)?;
+        Some(ChatSessionInfo {
+            id: id.to_owned(),
+            name: entry.name.clone(),
+            created_at: entry.created_at.timestamp_millis() as f64,
+            updated_at: entry.updated_at.timestamp_millis() as f64,
+            turn_count: entry
+                .session
+                .as_ref()
+                .map(|session| session.tree().len())
+                .unwrap_or_default(),
+            model: entry
+                .session
+                .as_ref()
+                .and_then(|session| session.model().map(str::to_owned)),
+            busy: entry.session.is_none(),
+        })
+    }
+
+    fn entry_mut(&mut self, id: &str) -> Result<&mut Entry, ChatError> {
+        self.entries
+            .get_mut(id)
+            .ok_or_else(|| ChatError::NotFound(id.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_out_busy() {
+        let mut registry = SessionRegistry::default();
+        let session = registry.check_out(DEFAULT_SESSION_ID).unwrap();
+        assert_eq!(
+            registry.check_out(DEFAULT_SESSION_ID).unwrap_err(),
+            ChatError::Busy(DEFAULT_SESSION_ID.to_owned())
+        );
+        assert!(registry.delete(DEFAULT_SESSION_ID).is_err());
+        assert!(registry.list()[0].busy);
+
+        registry.check_in(DEFAULT_SESSION_ID, session);
+        assert!(registry.check_out(DEFAULT_SESSION_ID).is_ok());
+    }
+
+    #[test]
+    fn test_default_session_before_use() {
+        let registry = SessionRegistry::default();
+        assert!(registry.turns(DEFAULT_SESSION_ID).unwrap().is_empty());
+        assert_eq!(
+            registry.turns("unknown").unwrap_err(),
+            ChatError::NotFound("unknown".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_sessions_are_independent() {
+        let mut registry = SessionRegistry::default();
+        let a = registry.create(Some("a".to_owned())).id;
+        let b = registry.create(None).id;
+        assert_ne!(a, b);
+        assert_eq!(registry.list().len(), 2);
+
+        let _session = registry.check_out(&a).unwrap();
+        assert!(registry.check_out(&b).is_ok());
+
+        registry.delete(&b).unwrap_err();
+        assert_eq!(
+            registry.check_out("unknown").unwrap_err(),
+            ChatError::NotFound("unknown".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut registry = SessionRegistry::default();
+        let id = registry.create(Some("a".to_owned())).id;
+        let stored = registry.to_stored(vec![]);
+        assert_eq!(stored.len(), 1);
+
+        let restored = SessionRegistry::restore(stored.clone());
+        assert_eq!(restored.list()[0].name, "a");
+        assert_eq!(restored.list()[0].id, id);
+
+        // Busy sessions keep what was stored before.
+        let _session = registry.check_out(&id).unwrap();
+        assert_eq!(registry.to_stored(vec![]).len(), 0);
+        assert_eq!(registry.to_stored(stored).len(), 1);
+    }
+
+    #[test]
+    fn test_unused_name() {
+        let mut registry = SessionRegistry::default();
+        let first = registry.create(None).id;
+        registry.create(None);
+        registry.delete(&first).unwrap();
+        let names = [registry.create(None).name, registry.create(None).name];
+        assert_eq!(names, ["Chat 1", "Chat 3"]);
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut registry = SessionRegistry::default();
+        let id = registry.create(None).id;
+        registry.delete(&id).unwrap();
+        assert!(registry.list().is_empty());
+        assert_eq!(registry.delete(&id), Err(ChatError::NotFound(id)));
+    }
+}
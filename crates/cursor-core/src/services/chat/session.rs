@@ -1,107 +1,220 @@
-use std::pin::pin;
-
-use futures::StreamExt;
-use node_bridge::prelude::*;
 use wasm_bindgen::{JsError, JsValue};
 
 use crate::{
+    context::get_extension_context,
+    model_configuration::Feature,
+    request::error_message,
     services::{
-        enveloped_message::{FilledPrompt, MessageContent},
-        stream::make_stream,
+        budget::ContextBudget, provider::provider_for, rules::load_rules,
+        stream::models::model_details::ModelDetails,
     },
     GenerateInput,
 };
 
 use super::models::{
-    conversation::{ConversationMessage, MessageType},
+    code_chunk::CodeChunk,
+    conversation::{Conversation, ConversationMessage},
     request_body::RequestBody,
+    tree::ConversationTree,
+    turn::{ChatTurn, TurnStatus},
 };
 
+/// What to reply to.
+#[derive(Debug, Clone)]
+pub enum ChatAction {
+    /// Reply to a new message.
+    Send,
+    /// Reply to the last message again, in a new branch next to the last
+    /// reply.
+    Regenerate,
+    /// Reply to `text` instead of the message of the turn at `index`, in a
+    /// new branch next to that turn.
+    Edit { index: usize, text: String },
+}
+
 #[derive(Debug)]
 pub struct Session {
-    request_body: Option<RequestBody>,
+    tree: ConversationTree,
 }
 
 impl Session {
-    fn body_with_input(&mut self, input: &GenerateInput) -> &RequestBody {
-        self.request_body = Some(
-            self.request_body
-                .take()
-                .map(|mut r| {
-                    r.conversation
-                        .push(ConversationMessage::new(MessageType::User, input.prompt()));
-                    r
-                })
-                .unwrap_or_else(|| RequestBody::new_with_input(input)),
-        )
-        .map(|mut r| {
-            // Add an empty bot message to the conversation.
-            r.conversation
-                .push(ConversationMessage::empty_message(MessageType::Bot));
-            r
-        });
-        self.request_body.as_ref().unwrap()
+    /// Returns the node to branch from and the message to reply to.
+    ///
+    /// New messages are taken from the input when the turn starts.
+    fn message_for(
+        &self,
+        action: ChatAction,
+    ) -> Result<(Option<usize>, Option<ConversationMessage>), String> {
+        let (index, text) = match action {
+            ChatAction::Send => return Ok((self.tree.parent_at(self.tree.len())?, None)),
+            ChatAction::Regenerate => {
+                let last = self
+                    .tree
+                    .len()
+                    .checked_sub(1)
+                    .ok_or("There is no message to reply to again")?;
+                (last, None)
+            }
+            ChatAction::Edit { index, text } => (index, Some(text)),
+        };
+        let parent = self.tree.parent_at(index)?;
+        // Only `Send` may continue past the last turn.
+        let mut message = self
+            .tree
+            .turn_at(index)
+            .ok_or_else(|| format!("No message at index {index}"))?
+            .message
+            .clone();
+        if let Some(text) = text {
+            message.text = text;
+        }
+        Ok((parent, Some(message)))
     }
 
-    fn push_bot_message(&mut self, message: String) {
-        self.request_body.as_mut().map(|r| {
-            r.conversation.last_mut().map(|m| {
-                if let MessageType::Bot = m.message_type {
-                    m.text = message
-                }
-            })
+    /// Adds a pending turn for the message, and returns the request with the
+    /// whole conversation.
+    ///
+    /// Without `message`, the message is taken from the input.
+    fn start_turn(
+        &mut self,
+        input: &GenerateInput,
+        model_details: ModelDetails,
+        parent: Option<usize>,
+        message: Option<ConversationMessage>,
+    ) -> RequestBody {
+        // The file context is taken from the editor on each message, so that
+        // it follows the file and the selection the user is looking at.
+        let mut request_body = RequestBody::new_with_input(input, model_details);
+        let mut input_message = request_body
+            .conversation
+            .pop()
+            .expect("the request contains the message");
+        let message = message.unwrap_or_else(|| {
+            // Only attach the selection again if it has changed.
+            if input_message.attached_code_chunks == self.latest_code_chunks() {
+                input_message.attached_code_chunks.clear();
+            }
+            input_message
         });
+
+        self.tree.push(
+            parent,
+            ChatTurn::new(message, request_body.model_details.name.clone()),
+        );
+        // The reply of the pending turn is the empty placeholder of the
+        // message being generated.
+        request_body.conversation = self.conversation();
+        request_body
     }
 }
 
 impl Session {
     pub fn new() -> Self {
-        Self { request_body: None }
+        Self {
+            tree: ConversationTree::default(),
+        }
     }
 
-    pub async fn send_message(&mut self, input: &GenerateInput) -> Result<(), JsValue> {
-        let request_body = self.body_with_input(input);
+    /// Continues a conversation restored from the storage.
+    pub fn restore(tree: ConversationTree) -> Self {
+        Self { tree }
+    }
 
-        #[cfg(debug_assertions)]
-        console::log_str(&serde_json::to_string_pretty(request_body).unwrap());
+    pub fn tree(&self) -> &ConversationTree {
+        &self.tree
+    }
 
-        let mut state = make_stream("/aiserver.v1.AiService/StreamChat", request_body).await?;
-        let result_stream = input.result_stream();
+    /// Switches to another alternative of the turn at `index` of the
+    /// conversation.
+    pub fn select_branch(&mut self, index: usize, alternative: usize) -> Result<(), String> {
+        self.tree.select(index, alternative)
+    }
+
+    /// Returns the messages along the selected branch.
+    pub fn conversation(&self) -> Conversation {
+        self.tree
+            .turns()
+            .into_iter()
+            .flat_map(ChatTurn::to_messages)
+            .collect()
+    }
 
-        let mut message = String::new();
-        {
-            let mut data_stream = pin!(state.data_stream());
-            while let Some(chunk) = data_stream.next().await {
-                if chunk.is_end() {
-                    break;
-                }
-                let data = chunk
-                    .utf8_string()
-                    .map_err(|e| JsError::new(&e.to_string()))?;
-                if data.is_empty() {
-                    continue;
-                }
-                if let Ok(prompt) = serde_json::from_str::<FilledPrompt>(&data) {
-                    #[cfg(debug_assertions)]
-                    console::log_str(&format!("prompt: \n{}", prompt.text));
-                    continue;
-                } else if let Ok(MessageContent { text, .. }) =
-                    serde_json::from_str::<MessageContent>(&data)
-                {
-                    #[cfg(debug_assertions)]
-                    console::log_str(&format!("wrote: {text}"));
-                    result_stream.write(&text);
-                    message.push_str(&data);
-                }
+    /// Returns the code attached to the latest message of the selected branch
+    /// that has some.
+    fn latest_code_chunks(&self) -> Vec<CodeChunk> {
+        self.tree
+            .turns()
+            .into_iter()
+            .rev()
+            .map(|turn| &turn.message.attached_code_chunks)
+            .find(|chunks| !chunks.is_empty())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the model that replied to the last message.
+    pub fn model(&self) -> Option<&str> {
+        self.tree.turns().last().map(|turn| turn.model.as_str())
+    }
+
+    /// Marks the pending turn as cancelled, if any, keeping what was replied
+    /// so far.
+    pub fn cancel_pending(&mut self) {
+        if let Some(turn) = self.tree.last_mut() {
+            if turn.status == TurnStatus::Pending {
+                turn.finish(TurnStatus::Cancelled);
             }
         }
+    }
 
-        state.complete().await?;
-        result_stream.end();
+    pub async fn send_message(
+        &mut self,
+        input: &GenerateInput,
+        action: ChatAction,
+    ) -> Result<(), JsValue> {
+        let configuration = get_extension_context().model_configuration();
+        let model = configuration.select_model_or_report(Feature::Chat)?;
+        let provider = provider_for(&configuration);
+        let (parent, message) = self.message_for(action).map_err(|err| JsError::new(&err))?;
+        // The history is kept whole, only the request is trimmed.
+        let mut request_body = self.start_turn(
+            input,
+            ModelDetails::new(model, configuration.api_key()),
+            parent,
+            message,
+        );
+        request_body.context = load_rules(input.workspace_directory().as_deref()).await;
+        request_body.fit_to_budget(ContextBudget::for_model(
+            &configuration,
+            &request_body.model_details,
+        ));
 
-        self.push_bot_message(message);
+        let result_stream = input.result_stream();
+        let turn = self.tree.last_mut().expect("the turn was just started");
+        // The reply is recorded as it arrives, so that it's kept if the
+        // message is cancelled or fails halfway.
+        let result = provider
+            .stream_chat(&request_body, &mut |text| {
+                result_stream.write(text);
+                turn.reply.push_str(text);
+            })
+            .await;
 
-        Ok(())
+        match result {
+            Ok(completion) => {
+                result_stream.end();
+                turn.reply = completion.text;
+                turn.filled_prompt = completion.filled_prompt;
+                turn.finish(TurnStatus::Complete);
+                Ok(())
+            }
+            Err(err) => {
+                turn.finish(TurnStatus::Errored {
+                    message: error_message(&err),
+                });
+                Err(err)
+            }
+        }
     }
 }
 
@@ -110,3 +223,79 @@ impl Default for Session {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::services::chat::models::conversation::MessageType;
+
+    use super::*;
+
+    #[test]
+    fn test_latest_code_chunks() {
+        let chunk = |line: &str| CodeChunk {
+            relative_workspace_path: "main.rs".to_owned(),
+            start_line: 0,
+            lines: vec![line.to_owned()],
+        };
+        let turn = |chunks: Vec<CodeChunk>| {
+            let mut message = ConversationMessage::new(MessageType::User, "?".to_owned());
+            message.attached_code_chunks = chunks;
+            ChatTurn::new(message, "gpt-4".to_owned())
+        };
+
+        let session = Session::restore(ConversationTree::from_turns(vec![
+            turn(vec![chunk("a")]),
+            turn(vec![chunk("b")]),
+            turn(vec![]),
+        ]));
+        assert_eq!(session.latest_code_chunks(), [chunk("b")]);
+        assert!(Session::new().latest_code_chunks().is_empty());
+    }
+
+    fn session_of(texts: &[&str]) -> Session {
+        Session::restore(ConversationTree::from_turns(
+            texts
+                .iter()
+                .map(|text| {
+                    let message = ConversationMessage::new(MessageType::User, text.to_string());
+                    ChatTurn::new(message, "gpt-4".to_owned())
+                })
+                .collect(),
+        ))
+    }
+
+    fn text_of(message: Option<ConversationMessage>) -> String {
+        message.map(|message| message.text).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_message_for() {
+        let session = session_of(&["a", "b", "c"]);
+
+        let (parent, message) = session.message_for(ChatAction::Send).unwrap();
+        assert_eq!(parent, Some(2));
+        assert!(message.is_none());
+
+        // The last message is replied to again, next to the last turn.
+        let (parent, message) = session.message_for(ChatAction::Regenerate).unwrap();
+        assert_eq!(parent, Some(1));
+        assert_eq!(text_of(message), "c");
+
+        let edit = |index| ChatAction::Edit {
+            index,
+            text: "edited".to_owned(),
+        };
+        let (parent, message) = session.message_for(edit(1)).unwrap();
+        assert_eq!(parent, Some(0));
+        assert_eq!(text_of(message), "edited");
+        let (parent, _) = session.message_for(edit(0)).unwrap();
+        assert_eq!(parent, None);
+        assert!(session.message_for(edit(4)).is_err());
+        assert_eq!(
+            session.message_for(edit(3)).unwrap_err(),
+            "No message at index 3"
+        );
+
+        assert!(Session::new().message_for(ChatAction::Regenerate).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! Persistence of chat sessions.
+//!
+//! The sessions are kept in the workspace storage, so that each workspace
+//! has its own chats and they survive reloading the window. Only the turns
+//! of the conversation are stored, the file context is rebuilt from the
+//! editor on the next message.
+//!
+//! The stored data is capped by size, the least recently updated sessions
+//! are evicted first.
+//!
+//! Sessions stored by older versions, as a list of messages or of turns, are
+//! read as a tree with a single branch.
+
+use node_bridge::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::context::get_extension_context;
+
+use super::models::{
+    conversation::{Conversation, MessageType},
+    tree::ConversationTree,
+    turn::{ChatTurn, TurnStatus},
+};
+
+const CHAT_SESSIONS_KEY: &str = "chat_sessions";
+/// Where unreadable sessions are moved, so that saving doesn't lose them.
+const UNREADABLE_CHAT_SESSIONS_KEY: &str = "chat_sessions_unreadable";
+/// The maximum size of the stored sessions, in bytes of JSON.
+const MAX_STORED_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "RawStoredSession")]
+pub struct StoredSession {
+    pub id: String,
+    pub name: String,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: i64,
+    /// Milliseconds since the Unix epoch.
+    pub updated_at: i64,
+    pub tree: ConversationTree,
+}
+
+#[derive(Deserialize)]
+struct RawStoredSession {
+    id: String,
+    name: String,
+    created_at: i64,
+    updated_at: i64,
+    #[serde(flatten)]
+    history: StoredHistory,
+}
+
+/// The ways the conversation has been stored.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredHistory {
+    Tree {
+        tree: ConversationTree,
+    },
+    Turns {
+        turns: Vec<ChatTurn>,
+    },
+    Conversation {
+        conversation: Conversation,
+        #[serde(default)]
+        model: Option<String>,
+    },
+}
+
+impl From<RawStoredSession> for StoredSession {
+    fn from(raw: RawStoredSession) -> Self {
+        let tree = match raw.history {
+            StoredHistory::Tree { tree } => tree,
+            StoredHistory::Turns { turns } => ConversationTree::from_turns(turns),
+            StoredHistory::Conversation {
+                conversation,
+                model,
+            } => ConversationTree::from_turns(conversation_turns(
+                conversation,
+                model.unwrap_or_default(),
+            )),
+        };
+        Self {
+            id: raw.id,
+            name: raw.name,
+            created_at: raw.created_at,
+            updated_at: raw.updated_at,
+            tree,
+        }
+    }
+}
+
+/// Pairs the messages of the user with the replies to them.
+fn conversation_turns(conversation: Conversation, model: String) -> Vec<ChatTurn> {
+    let mut turns = Vec::<ChatTurn>::new();
+    for message in conversation {
+        match message.message_type {
+            MessageType::User => turns.push(ChatTurn::new(message, model.clone())),
+            MessageType::Bot => {
+                if let Some(turn) = turns.last_mut() {
+                    turn.reply.push_str(&message.text);
+                }
+            }
+        }
+    }
+    for turn in &mut turns {
+        turn.finish(TurnStatus::Complete);
+    }
+    turns
+}
+
+pub fn load_sessions() -> Vec<StoredSession> {
+    let storage = get_extension_context().workspace_storage();
+    let Some(data) = storage.get(CHAT_SESSIONS_KEY) else {
+        return vec![];
+    };
+    match parse_sessions(&data) {
+        Ok((sessions, unreadable)) => {
+            if !unreadable.is_empty() {
+                console::warn_str(&format!(
+                    "Failed to read {} of the chat sessions, they are moved to \
+                    {UNREADABLE_CHAT_SESSIONS_KEY}",
+                    unreadable.len()
+                ));
+                if let Ok(data) = serde_json::to_string(&unreadable) {
+                    storage.update(UNREADABLE_CHAT_SESSIONS_KEY, Some(&data));
+                }
+            }
+            sessions
+        }
+        Err(err) => {
+            console::warn_str(&format!(
+                "Failed to read the chat sessions, they are moved to \
+                {UNREADABLE_CHAT_SESSIONS_KEY}: {err}"
+            ));
+            storage.update(UNREADABLE_CHAT_SESSIONS_KEY, Some(&data));
+            vec![]
+        }
+    }
+}
+
+/// Returns the readable sessions, along with the data of the unreadable ones.
+fn parse_sessions(data: &str) -> serde_json::Result<(Vec<StoredSession>, Vec<serde_json::Value>)> {
+    let mut sessions = vec![];
+    let mut unreadable = vec![];
+    for value in serde_json::from_str::<Vec<serde_json::Value>>(data)? {
+        match StoredSession::deserialize(&value) {
+            Ok(session) => sessions.push(session),
+            Err(_) => unreadable.push(value),
+        }
+    }
+    Ok((sessions, unreadable))
+}
+
+pub fn save_sessions(mut sessions: Vec<StoredSession>) {
+    cap_sessions(&mut sessions, MAX_STORED_BYTES);
+    let storage = get_extension_context().workspace_storage();
+    if sessions.is_empty() {
+        storage.update(CHAT_SESSIONS_KEY, None);
+    } else if let Ok(data) = serde_json::to_string(&sessions) {
+        storage.update(CHAT_SESSIONS_KEY, Some(&data));
+    }
+}
+
+fn stored_size(session: &StoredSession) -> usize {
+    serde_json::to_string(session)
+        .map(|data| data.len())
+        .unwrap_or_default()
+}
+
+/// Evicts the least recently updated sessions until the rest fit in
+/// `max_bytes`.
+///
+/// The most recent session is never evicted, if it doesn't fit on its own
+/// only its selected branch is kept, without the oldest turns.
+fn cap_sessions(sessions: &mut Vec<StoredSession>, max_bytes: usize) {
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+
+    let mut total = 0;
+    let mut kept = 0;
+    for session in sessions.iter() {
+        let size = stored_size(session) + 1;
+        if total + size > max_bytes {
+            break;
+        }
+        total += size;
+        kept += 1;
+    }
+    if kept > 0 {
+        sessions.truncate(kept);
+        return;
+    }
+
+    sessions.truncate(1);
+    if let Some(session) = sessions.first_mut() {
+        let mut turns = session
+            .tree
+            .turns()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        session.tree = ConversationTree::from_turns(turns.clone());
+        while stored_size(session) > max_bytes && !turns.is_empty() {
+            turns.remove(0);
+            session.tree = ConversationTree::from_turns(turns.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::chat::models::conversation::ConversationMessage;
+
+    use super::*;
+
+    fn session(id: &str, updated_at: i64, turns: usize) -> StoredSession {
+        StoredSession {
+            id: id.to_owned(),
+            name: id.to_owned(),
+            created_at: 0,
+            updated_at,
+            tree: ConversationTree::from_turns(
+                (0..turns)
+                    .map(|i| {
+                        let message =
+                            ConversationMessage::new(MessageType::User, format!("message {i:03}"));
+                        ChatTurn::new(message, "gpt-4".to_owned())
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_cap_sessions_evicts_oldest() {
+        let mut sessions = vec![
+            session("a", 1, 10),
+            session("b", 3, 10),
+            session("c", 2, 10),
+        ];
+        let max_bytes = stored_size(&sessions[0]) * 2 + 2;
+        cap_sessions(&mut sessions, max_bytes);
+        let ids = sessions.iter().map(|s| s.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["b", "c"]);
+    }
+
+    #[test]
+    fn test_cap_sessions_trims_newest() {
+        let mut sessions = vec![session("a", 1, 10), session("b", 2, 100)];
+        let max_bytes = stored_size(&session("b", 2, 10));
+        cap_sessions(&mut sessions, max_bytes);
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, "b");
+        let turns = sessions[0].tree.turns();
+        assert_eq!(turns.len(), 10);
+        assert_eq!(turns[0].message.text, "message 090");
+    }
+
+    #[test]
+    fn test_read_older_formats() {
+        let turns = serde_json::to_value(session("a", 1, 2).tree.turns()).unwrap();
+        let data = serde_json::json!({
+            "id": "a", "name": "a", "created_at": 0, "updated_at": 1,
+            "turns": turns,
+        });
+        let restored = serde_json::from_value::<StoredSession>(data).unwrap();
+        assert_eq!(restored.tree.len(), 2);
+
+        let data = serde_json::json!({
+            "id": "a", "name": "a", "created_at": 0, "updated_at": 1,
+            "model": "gpt-4",
+            "conversation": [
+                { "type": "MESSAGE_TYPE_HUMAN", "text": "hi" },
+                { "type": "MESSAGE_TYPE_AI", "text": "Hello" },
+                { "type": "MESSAGE_TYPE_HUMAN", "text": "bye" },
+            ],
+        });
+        let restored = serde_json::from_value::<StoredSession>(data).unwrap();
+        let turns = restored.tree.turns();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0].reply, "Hello");
+        assert_eq!(turns[0].model, "gpt-4");
+        assert_eq!(turns[1].status, TurnStatus::Complete);
+    }
+
+    #[test]
+    fn test_drop_corrupted_sessions() {
+        let mut data = serde_json::to_value([session("a", 1, 2), session("b", 2, 2)]).unwrap();
+        data[0]["tree"]["current"] = serde_json::json!(7);
+        let (sessions, unreadable) = parse_sessions(&data.to_string()).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, "b");
+        assert_eq!(unreadable.len(), 1);
+        assert_eq!(unreadable[0]["id"], "a");
+
+        assert!(parse_sessions("{").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = serde_json::to_string(&session("a", 1, 2)).unwrap();
+        let restored = serde_json::from_str::<StoredSession>(&data).unwrap();
+        let turns = restored.tree.turns();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[1].message.text, "message 001");
+        assert_eq!(turns[1].status, TurnStatus::Pending);
+    }
+}
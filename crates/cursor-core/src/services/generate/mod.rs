@@ -1,21 +1,18 @@
-mod request_body;
+pub mod request_body;
 
-use std::{future::IntoFuture, pin::pin};
+use std::future::IntoFuture;
 
-use futures::{
-    future::{select, Either},
-    StreamExt,
-};
+use futures::future::{select, Either};
 use node_bridge::{futures::Defer, prelude::*};
-use wasm_bindgen::{prelude::*, JsError, JsValue};
+use wasm_bindgen::{prelude::*, JsValue};
 
-use crate::GenerateInput;
+use crate::{context::get_extension_context, model_configuration::Feature, GenerateInput};
 
 use self::request_body::RequestBody;
 
 use super::{
-    enveloped_message::{FilledPrompt, MessageContent},
-    stream::make_stream,
+    budget::ContextBudget, provider::provider_for, rules::load_rules,
+    stream::models::model_details::ModelDetails,
 };
 
 #[derive(Debug, Clone)]
@@ -23,48 +20,29 @@ pub struct CodeGenerateService;
 
 impl CodeGenerateService {
     pub async fn generate(input: &GenerateInput) -> Result<(), JsValue> {
-        let mut state = make_stream(
-            &format!(
-                "/aiserver.v1.AiService/Stream{}",
-                if input.selection_range().is_empty() {
-                    "Generate"
-                } else {
-                    "Edit"
-                }
-            ),
-            &RequestBody::new_with_input(input),
-        )
-        .await?;
+        let configuration = get_extension_context().model_configuration();
+        let is_edit = !input.selection_range().is_empty();
+        let model = configuration.select_model_or_report(if is_edit {
+            Feature::Edit
+        } else {
+            Feature::Generate
+        })?;
+        let provider = provider_for(&configuration);
+        let mut request_body =
+            RequestBody::new_with_input(input, ModelDetails::new(model, configuration.api_key()));
+        request_body.context = load_rules(input.workspace_directory().as_deref()).await;
+        request_body.fit_to_budget(ContextBudget::for_model(
+            &configuration,
+            &request_body.model_details,
+        ));
 
         let result_stream = input.result_stream();
-        {
-            let mut data_stream = pin!(state.data_stream());
-            while let Some(chunk) = data_stream.next().await {
-                if chunk.is_end() {
-                    break;
-                }
-                let data = chunk
-                    .utf8_string()
-                    .map_err(|e| JsError::new(&e.to_string()))?;
-                if data.is_empty() {
-                    continue;
-                }
-                if let Ok(prompt) = serde_json::from_str::<FilledPrompt>(&data) {
-                    #[cfg(debug_assertions)]
-                    console::log_str(&format!("prompt: \n{}", prompt.text));
-                    continue;
-                } else if let Ok(MessageContent { text, .. }) =
-                    serde_json::from_str::<MessageContent>(&data)
-                {
-                    #[cfg(debug_assertions)]
-                    console::log_str(&format!("wrote: {text}"));
-                    result_stream.write(&text);
-                }
-            }
+        let mut sink = |text: &str| result_stream.write(text);
+        if is_edit {
+            provider.stream_edit(&request_body, &mut sink).await?;
+        } else {
+            provider.stream_generate(&request_body, &mut sink).await?;
         }
-
-        // Make sure the response is fully received without errors.
-        state.complete().await?;
         result_stream.end();
 
         Ok(())
@@ -1,9 +1,13 @@
 use serde::Serialize;
 
 use crate::{
-    context::get_extension_context,
-    services::stream::models::{
-        current_file::CurrentFile, explicit_context::ExplicitContext, model_details::ModelDetails,
+    services::{
+        budget::{count_tokens, ContextBudget},
+        stream::models::{
+            current_file::CurrentFile, explicit_context::ExplicitContext,
+            model_details::ModelDetails,
+        },
+        workspace_path::workspace_folder_name,
     },
     GenerateInput,
 };
@@ -15,6 +19,7 @@ pub struct RequestBody {
     pub current_file: CurrentFile,
     pub model_details: ModelDetails,
 
+    /// The name of the workspace folder, not its absolute path.
     #[serde(rename = "workspaceRootPath")]
     pub root_path: String,
 
@@ -23,25 +28,28 @@ pub struct RequestBody {
 }
 
 impl RequestBody {
-    pub fn new_with_input(input: &GenerateInput) -> Self {
-        let context = get_extension_context();
-        let configuration = context.model_configuration();
+    pub fn new_with_input(input: &GenerateInput, model_details: ModelDetails) -> Self {
         Self {
             query: input.prompt(),
             current_file: CurrentFile {
                 content: input.document_text(),
                 language_id: input.language_id(),
-                relative_workspace_path: input.file_path(),
+                relative_workspace_path: input.relative_file_path(),
                 selection: input.selection_range().into(),
                 cursor: input.cursor().into(),
             },
-            model_details: ModelDetails {
-                name: configuration.model_name(),
-                ghost_mode: true,
-                api_key: configuration.api_key(),
-            },
-            root_path: input.workspace_directory().unwrap_or_default(),
-            context: ExplicitContext {},
+            model_details,
+            root_path: workspace_folder_name(input.workspace_directory().as_deref()),
+            context: ExplicitContext::default(),
         }
     }
+
+    /// Trims the file context to fit in the budget along with the query and
+    /// the rules.
+    pub fn fit_to_budget(&mut self, budget: ContextBudget) {
+        let budget = budget
+            .prompt_tokens
+            .saturating_sub(count_tokens(&self.query) + self.context.token_count());
+        self.current_file.fit_to_budget(budget);
+    }
 }
@@ -1,4 +1,10 @@
+pub mod account;
+mod budget;
 pub mod chat;
 mod enveloped_message;
 pub mod generate;
-mod stream;
+mod openai;
+pub mod provider;
+mod rules;
+pub(crate) mod stream;
+pub(crate) mod workspace_path;
@@ -0,0 +1,96 @@
+const FENCE: &str = "```";
+
+/// Drops Markdown code fence lines from streamed code, in case the model
+/// wraps its reply in a code block despite being told not to.
+///
+/// Text is passed through as soon as its line can no longer be a fence.
+#[derive(Debug, Default)]
+pub struct CodeFenceFilter {
+    pending: String,
+    passthrough: bool,
+}
+
+impl CodeFenceFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a piece of the reply and returns the text ready to be written.
+    pub fn push(&mut self, text: &str) -> String {
+        let mut output = String::new();
+        for c in text.chars() {
+            if self.passthrough {
+                output.push(c);
+                self.passthrough = c != '\n';
+                continue;
+            }
+
+            self.pending.push(c);
+            if c == '\n' {
+                if !is_fence(&self.pending) {
+                    output.push_str(&self.pending);
+                }
+                self.pending.clear();
+            } else if !may_be_fence(&self.pending) {
+                output.push_str(&self.pending);
+                self.pending.clear();
+                self.passthrough = true;
+            }
+        }
+        output
+    }
+
+    /// Returns the rest of the reply when it ends.
+    pub fn finish(&mut self) -> String {
+        let pending = std::mem::take(&mut self.pending);
+        if is_fence(&pending) {
+            String::new()
+        } else {
+            pending
+        }
+    }
+}
+
+fn is_fence(line: &str) -> bool {
+    line.trim_start().starts_with(FENCE)
+}
+
+fn may_be_fence(partial_line: &str) -> bool {
+    let trimmed = partial_line.trim_start();
+    FENCE.starts_with(trimmed) || trimmed.starts_with(FENCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(pieces: &[&str]) -> String {
+        let mut filter = CodeFenceFilter::new();
+        let mut output = pieces
+            .iter()
+            .map(|piece| filter.push(piece))
+            .collect::<String>();
+        output.push_str(&filter.finish());
+        output
+    }
+
+    #[test]
+    fn test_strip_fences() {
+        assert_eq!(
+            filter(&["``", "`rust\nfn ", "main() {}\n", "``", "`"]),
+            "fn main() {}\n"
+        );
+        assert_eq!(
+            filter(&["let a = `b`;\n", "  let c;"]),
+            "let a = `b`;\n  let c;"
+        );
+    }
+
+    #[test]
+    fn test_passthrough_early() {
+        let mut filter = CodeFenceFilter::new();
+        assert_eq!(filter.push("  "), "");
+        assert_eq!(filter.push("fn"), "  fn");
+        assert_eq!(filter.push(" main"), " main");
+    }
+}
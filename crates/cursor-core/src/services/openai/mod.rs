@@ -0,0 +1,368 @@
+//! A client of OpenAI-compatible `/chat/completions` endpoints, which is
+//! requested directly instead of going through the Cursor server.
+//!
+//! Local servers like Ollama and llama.cpp expose the same API, so they're
+//! served by this client as well.
+
+pub mod fence;
+pub mod models;
+pub mod prompt;
+mod sse;
+
+use futures::{
+    future::LocalBoxFuture,
+    stream::{self, LocalBoxStream},
+    FutureExt, StreamExt,
+};
+use js_sys::Uint8Array;
+use node_bridge::{
+    http_client::{HttpMethod, HttpRequest},
+    prelude::*,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    model_configuration::{ModelConfiguration, Provider},
+    model_registry::ModelInfo,
+    request::{error_message, JsonSendable},
+    services::stream::models::model_details::ModelDetails,
+};
+
+use self::{
+    models::{ChatCompletionChunk, ChatCompletionRequest, ChatMessage, ErrorResponse, ModelList},
+    sse::SseDecoder,
+};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+/// The default address of Ollama, llama.cpp listens on port 8080 instead.
+pub const DEFAULT_LOCAL_BASE_URL: &str = "http://127.0.0.1:11434/v1";
+const DONE_EVENT: &str = "[DONE]";
+
+/// A response as seen by the client.
+pub struct RawResponse {
+    status_code: u16,
+    /// The chunks of the body.
+    body: LocalBoxStream<'static, Vec<u8>>,
+}
+
+impl RawResponse {
+    async fn text(self) -> String {
+        let body = self.body.concat().await;
+        String::from_utf8_lossy(&body).into_owned()
+    }
+}
+
+/// Sends the requests of the client, over `node:http` outside of tests.
+pub trait Transport {
+    fn send(&self, request: HttpRequest) -> LocalBoxFuture<'_, Result<RawResponse, String>>;
+}
+
+pub struct NodeTransport;
+
+impl Transport for NodeTransport {
+    fn send(&self, request: HttpRequest) -> LocalBoxFuture<'_, Result<RawResponse, String>> {
+        async move {
+            let response = request.send().await.map_err(|err| error_message(&err))?;
+            let status_code = response.status_code();
+            let body = stream::unfold(response, |mut response| async move {
+                let chunk = response.body().next().await?;
+                Some((Uint8Array::new(&chunk).to_vec(), response))
+            });
+            Ok(RawResponse {
+                status_code,
+                body: body.boxed_local(),
+            })
+        }
+        .boxed_local()
+    }
+}
+
+pub struct OpenAiClient<T = NodeTransport> {
+    base_url: String,
+    api_key: Option<String>,
+    transport: T,
+}
+
+impl OpenAiClient {
+    pub fn from_configuration(configuration: &ModelConfiguration) -> Self {
+        let base_url = configuration.base_url().filter(|url| !url.is_empty());
+        match configuration.provider() {
+            Provider::Local => Self {
+                base_url: base_url.unwrap_or_else(|| DEFAULT_LOCAL_BASE_URL.to_owned()),
+                // Never hand the OpenAI key to a local server.
+                api_key: None,
+                transport: NodeTransport,
+            },
+            _ => Self {
+                base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_owned()),
+                api_key: configuration.api_key(),
+                transport: NodeTransport,
+            },
+        }
+    }
+}
+
+impl<T: Transport> OpenAiClient<T> {
+    /// Returns the models available on the server.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>, JsValue> {
+        self.try_list_models().await.map_err(to_js_error)
+    }
+
+    async fn try_list_models(&self) -> Result<Vec<ModelInfo>, String> {
+        let url = format!("{}/models", self.base_url.trim_end_matches('/'));
+        let mut request = HttpRequest::new(&url);
+        if let Some(api_key) = &self.api_key {
+            request = request.add_header("Authorization", &format!("Bearer {api_key}"));
+        }
+
+        let data = self.send(request).await?.text().await;
+        let models = serde_json::from_str::<ModelList>(&data).map_err(|err| err.to_string())?;
+        Ok(models
+            .data
+            .into_iter()
+            .map(|model| {
+                let context_window = model.context_window();
+                ModelInfo::new(model.id, context_window)
+            })
+            .collect())
+    }
+
+    /// Streams the reply of `model` to `messages`, calling `on_text` with
+    /// each piece of it as it arrives.
+    ///
+    /// Returns the whole reply.
+    pub async fn stream_chat<F>(
+        &self,
+        model: &ModelDetails,
+        messages: &[ChatMessage],
+        on_text: F,
+    ) -> Result<String, JsValue>
+    where
+        F: FnMut(&str),
+    {
+        let reply = self
+            .try_stream_chat(model, messages, on_text)
+            .await
+            .map_err(to_js_error)?;
+
+        #[cfg(debug_assertions)]
+        console::log_str(&format!("reply: {reply}"));
+        Ok(reply)
+    }
+
+    async fn try_stream_chat<F>(
+        &self,
+        model: &ModelDetails,
+        messages: &[ChatMessage],
+        mut on_text: F,
+    ) -> Result<String, String>
+    where
+        F: FnMut(&str),
+    {
+        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        let mut request = HttpRequest::new(&url)
+            .set_method(HttpMethod::Post)
+            .add_header("accept", "text/event-stream")
+            .set_json_body(&ChatCompletionRequest {
+                model: &model.name,
+                messages,
+                stream: true,
+                temperature: model.temperature,
+                max_tokens: model.max_tokens,
+                stop: &model.stop,
+            });
+        if let Some(api_key) = &self.api_key {
+            request = request.add_header("Authorization", &format!("Bearer {api_key}"));
+        }
+
+        let mut response = self.send(request).await?;
+
+        let mut reply = String::new();
+        let mut decoder = SseDecoder::new();
+        'stream: while let Some(chunk) = response.body.next().await {
+            for data in decoder.push(&chunk) {
+                if data == DONE_EVENT {
+                    break 'stream;
+                }
+                let text = parse_event(&data)?;
+                on_text(&text);
+                reply.push_str(&text);
+            }
+        }
+        if let Some(data) = decoder.finish().filter(|data| data != DONE_EVENT) {
+            let text = parse_event(&data)?;
+            on_text(&text);
+            reply.push_str(&text);
+        }
+        Ok(reply)
+    }
+
+    /// Sends the request, failing unless the server returns 200.
+    async fn send(&self, request: HttpRequest) -> Result<RawResponse, String> {
+        let response = self
+            .transport
+            .send(request)
+            .await
+            .map_err(|reason| format!("Cannot reach {}: {reason}", self.base_url))?;
+        let status_code = response.status_code;
+        if status_code != 200 {
+            let data = response.text().await;
+            let reason = serde_json::from_str::<ErrorResponse>(&data)
+                .map(|response| response.error.message)
+                .unwrap_or(data);
+            return Err(format!(
+                "Server returned status code {status_code}: {reason}"
+            ));
+        }
+
+        Ok(response)
+    }
+}
+
+fn to_js_error(reason: String) -> JsValue {
+    js_sys::Error::new(&reason).into()
+}
+
+/// Returns the text carried by an event, errors may be reported in the
+/// middle of the stream.
+fn parse_event(data: &str) -> Result<String, String> {
+    if let Ok(chunk) = serde_json::from_str::<ChatCompletionChunk>(data) {
+        return Ok(chunk
+            .choices
+            .into_iter()
+            .filter_map(|choice| choice.delta.content)
+            .collect());
+    }
+    let reason = serde_json::from_str::<ErrorResponse>(data)
+        .map(|response| response.error.message)
+        .unwrap_or_else(|_| format!("unexpected event: {data}"));
+    Err(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque};
+
+    use futures::executor::block_on;
+    use serde_json::Value;
+
+    use super::{models::Role, *};
+
+    /// Serves canned responses and records the requests.
+    #[derive(Default)]
+    struct MockServer {
+        requests: RefCell<Vec<HttpRequest>>,
+        responses: RefCell<VecDeque<(u16, Vec<&'static str>)>>,
+    }
+
+    impl MockServer {
+        fn respond(self, status_code: u16, chunks: Vec<&'static str>) -> Self {
+            self.responses.borrow_mut().push_back((status_code, chunks));
+            self
+        }
+    }
+
+    impl Transport for MockServer {
+        fn send(&self, request: HttpRequest) -> LocalBoxFuture<'_, Result<RawResponse, String>> {
+            self.requests.borrow_mut().push(request);
+            let response = self.responses.borrow_mut().pop_front();
+            async move {
+                let (status_code, chunks) = response.ok_or("connection refused")?;
+                let chunks = chunks.into_iter().map(|chunk| chunk.as_bytes().to_vec());
+                Ok(RawResponse {
+                    status_code,
+                    body: stream::iter(chunks).boxed_local(),
+                })
+            }
+            .boxed_local()
+        }
+    }
+
+    fn mock_client(server: MockServer) -> OpenAiClient<MockServer> {
+        OpenAiClient {
+            base_url: "http://127.0.0.1:11434/v1/".to_owned(),
+            api_key: Some("sk-test".to_owned()),
+            transport: server,
+        }
+    }
+
+    fn model() -> ModelDetails {
+        ModelDetails {
+            name: "gpt-4".to_owned(),
+            ghost_mode: true,
+            api_key: None,
+            temperature: Some(0.2),
+            max_tokens: None,
+            stop: vec![],
+        }
+    }
+
+    fn chat(client: &OpenAiClient<MockServer>) -> (Result<String, String>, Vec<String>) {
+        let messages = [ChatMessage::new(Role::User, "hi".to_owned())];
+        let mut pieces = vec![];
+        let reply = block_on(
+            client.try_stream_chat(&model(), &messages, |text| pieces.push(text.to_owned())),
+        );
+        (reply, pieces)
+    }
+
+    #[test]
+    fn test_stream_chat() {
+        let client = mock_client(MockServer::default().respond(
+            200,
+            vec![
+                "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+                "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\nda",
+                "ta: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n",
+                "data: [DONE]\n\n",
+            ],
+        ));
+        let (reply, pieces) = chat(&client);
+        assert_eq!(reply.unwrap(), "Hello");
+        assert_eq!(pieces, ["", "Hel", "lo"]);
+
+        let requests = client.transport.requests.borrow();
+        let request = &requests[0];
+        assert_eq!(request.url(), "http://127.0.0.1:11434/v1/chat/completions");
+        assert_eq!(request.method(), HttpMethod::Post);
+        assert_eq!(request.header("Authorization"), Some("Bearer sk-test"));
+        let body = serde_json::from_slice::<Value>(request.body().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "model": "gpt-4",
+                "messages": [{ "role": "user", "content": "hi" }],
+                "stream": true,
+                "temperature": 0.2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_stream_chat_errors() {
+        let client = mock_client(MockServer::default().respond(
+            401,
+            vec![r#"{"error":{"message":"Incorrect API key provided"}}"#],
+        ));
+        let (reply, _) = chat(&client);
+        assert_eq!(
+            reply.unwrap_err(),
+            "Server returned status code 401: Incorrect API key provided"
+        );
+
+        // Errors may also arrive in the middle of the stream.
+        let client = mock_client(MockServer::default().respond(
+            200,
+            vec![
+                "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+                "data: {\"error\":{\"message\":\"overloaded\"}}\n\n",
+            ],
+        ));
+        let (reply, pieces) = chat(&client);
+        assert_eq!(reply.unwrap_err(), "overloaded");
+        assert_eq!(pieces, ["Hel"]);
+
+        let (reply, _) = chat(&client);
+        assert!(reply.unwrap_err().starts_with("Cannot reach"));
+    }
+}
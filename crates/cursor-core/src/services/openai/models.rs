@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: String) -> Self {
+        Self { role, content }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatCompletionRequest<'a> {
+    pub model: &'a str,
+    pub messages: &'a [ChatMessage],
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    pub stop: &'a [String],
+}
+
+/// A chunk of a streamed `/chat/completions` response.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionChunk {
+    pub choices: Vec<ChunkChoice>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChunkChoice {
+    pub delta: Delta,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Delta {
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ErrorResponse {
+    pub error: ErrorDetails,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ErrorDetails {
+    pub message: String,
+}
+
+/// The response of the `/models` endpoint.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelList {
+    pub data: Vec<ModelObject>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelObject {
+    pub id: String,
+    /// Reported by some gateways, the official API doesn't.
+    pub context_length: Option<u32>,
+    /// Reported by the llama.cpp server.
+    pub meta: Option<ModelMeta>,
+}
+
+impl ModelObject {
+    pub fn context_window(&self) -> Option<u32> {
+        self.context_length
+            .or_else(|| self.meta.as_ref().and_then(|meta| meta.n_ctx_train))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelMeta {
+    pub n_ctx_train: Option<u32>,
+}
@@ -0,0 +1,262 @@
+//! Prompts built locally for the providers that talk to the model directly,
+//! in place of the ones filled by the Cursor server.
+
+use crate::services::{
+    chat::models::conversation::{ConversationMessage, MessageType},
+    stream::models::{
+        current_file::{CurrentFile, Position},
+        explicit_context::ExplicitContext,
+    },
+};
+
+use super::models::{ChatMessage, Role};
+
+const GENERATE_SYSTEM_PROMPT: &str = "You are an expert programmer working in a code editor. \
+    Reply with the code only, without any explanation or Markdown code fences.";
+const CHAT_SYSTEM_PROMPT: &str = "You are an expert programmer helping the user with their \
+    code in a code editor. Answer concisely in Markdown.";
+
+const CURSOR_MARKER: &str = "<CURSOR>";
+const SELECTION_START_MARKER: &str = "<SELECTION>";
+const SELECTION_END_MARKER: &str = "</SELECTION>";
+
+/// Appends the rules of the project to the system prompt.
+fn system_message(prompt: &str, context: &ExplicitContext) -> ChatMessage {
+    let content = if context.is_empty() {
+        prompt.to_owned()
+    } else {
+        format!(
+            "{prompt}\n\nFollow these instructions of the project:\n{}",
+            context.context
+        )
+    };
+    ChatMessage::new(Role::System, content)
+}
+
+/// Builds the messages to insert code at the cursor.
+pub fn generate_messages(
+    query: &str,
+    current_file: &CurrentFile,
+    context: &ExplicitContext,
+) -> Vec<ChatMessage> {
+    code_messages(
+        format!("Write the code to insert at {CURSOR_MARKER}: {query}"),
+        current_file,
+        context,
+    )
+}
+
+/// Builds the messages to rewrite the selection.
+pub fn edit_messages(
+    query: &str,
+    current_file: &CurrentFile,
+    context: &ExplicitContext,
+) -> Vec<ChatMessage> {
+    code_messages(
+        format!(
+            "Rewrite the code between {SELECTION_START_MARKER} and {SELECTION_END_MARKER}: \
+            {query}\nReply with the code to replace the selection."
+        ),
+        current_file,
+        context,
+    )
+}
+
+fn code_messages(
+    instruction: String,
+    current_file: &CurrentFile,
+    context: &ExplicitContext,
+) -> Vec<ChatMessage> {
+    vec![
+        system_message(GENERATE_SYSTEM_PROMPT, context),
+        ChatMessage::new(
+            Role::User,
+            format!("{}\n\n{instruction}", file_context(current_file)),
+        ),
+    ]
+}
+
+/// Builds the messages of a conversation about the current file.
+///
+/// A trailing empty bot message, which is the placeholder for the reply
+/// being generated, is skipped.
+pub fn chat_messages(
+    current_file: &CurrentFile,
+    context: &ExplicitContext,
+    conversation: &[ConversationMessage],
+) -> Vec<ChatMessage> {
+    let mut messages = vec![
+        system_message(CHAT_SYSTEM_PROMPT, context),
+        ChatMessage::new(Role::User, file_context(current_file)),
+    ];
+    messages.extend(
+        conversation
+            .iter()
+            .filter(|message| !message.text.is_empty())
+            .map(|message| {
+                let role = match message.message_type {
+                    MessageType::User => Role::User,
+                    MessageType::Bot => Role::Assistant,
+                };
+                ChatMessage::new(role, message_text(message))
+            }),
+    );
+    messages
+}
+
+/// Appends the attached code to the text of the message.
+fn message_text(message: &ConversationMessage) -> String {
+    let mut text = message.text.clone();
+    for chunk in &message.attached_code_chunks {
+        text.push_str(&format!(
+            "\n\n`{}` from line {}:\n```\n{}\n```",
+            chunk.relative_workspace_path,
+            chunk.start_line + 1,
+            chunk.lines.join("\n")
+        ));
+    }
+    text
+}
+
+/// Describes the file with the cursor or the selection marked.
+fn file_context(current_file: &CurrentFile) -> String {
+    let content = &current_file.content;
+    let start = offset_at(content, &current_file.selection.start);
+    let end = offset_at(content, &current_file.selection.end).max(start);
+    let marked = if start == end {
+        let cursor = offset_at(content, &current_file.cursor);
+        format!(
+            "{}{CURSOR_MARKER}{}",
+            &content[..cursor],
+            &content[cursor..]
+        )
+    } else {
+        format!(
+            "{}{SELECTION_START_MARKER}{}{SELECTION_END_MARKER}{}",
+            &content[..start],
+            &content[start..end],
+            &content[end..]
+        )
+    };
+    format!(
+        "Current file `{}`:\n```{}\n{marked}\n```",
+        current_file.relative_workspace_path, current_file.language_id
+    )
+}
+
+/// Converts a position, whose column counts UTF-16 code units like the
+/// editor does, to a byte offset in `content`, clamped to its bounds.
+fn offset_at(content: &str, position: &Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match content[line_start..].find('\n') {
+            Some(index) => line_start += index + 1,
+            None => return content.len(),
+        }
+    }
+
+    let mut column = 0;
+    for (index, c) in content[line_start..].char_indices() {
+        if column >= position.column || c == '\n' {
+            return line_start + index;
+        }
+        column += c.len_utf16();
+    }
+    content.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::{
+        chat::models::code_chunk::CodeChunk, stream::models::current_file::Selection,
+    };
+
+    use super::*;
+
+    fn make_file(content: &str, start: (usize, usize), end: (usize, usize)) -> CurrentFile {
+        let position = |(line, column)| Position { line, column };
+        CurrentFile {
+            content: content.to_owned(),
+            language_id: "rust".to_owned(),
+            relative_workspace_path: "src/main.rs".to_owned(),
+            selection: Selection {
+                start: position(start),
+                end: position(end),
+            },
+            cursor: position(end),
+        }
+    }
+
+    #[test]
+    fn test_offset_at() {
+        let content = "fn main() {\n    let é = 1;\n}";
+        let offset = |line, column| offset_at(content, &Position { line, column });
+        assert_eq!(offset(0, 0), 0);
+        assert_eq!(offset(0, 3), 3);
+        assert_eq!(offset(1, 9), 22);
+        // Past the end of the line.
+        assert_eq!(offset(1, 100), 27);
+        assert_eq!(offset(2, 1), 29);
+        assert_eq!(offset(10, 0), content.len());
+    }
+
+    #[test]
+    fn test_code_messages() {
+        let file = make_file("let a = 1;\nlet b = 2;\n", (1, 0), (1, 10));
+        let messages = edit_messages("rename b to c", &file, &ExplicitContext::default());
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, Role::System);
+        assert!(messages[1]
+            .content
+            .contains("let a = 1;\n<SELECTION>let b = 2;</SELECTION>\n"));
+        assert!(messages[1].content.ends_with(
+            "Rewrite the code between <SELECTION> and </SELECTION>: rename b to c\n\
+            Reply with the code to replace the selection."
+        ));
+
+        let file = make_file("let a = 1;\n", (1, 0), (1, 0));
+        let rules = ExplicitContext::new("Use tabs.".to_owned());
+        let messages = generate_messages("add b", &file, &rules);
+        assert!(messages[0].content.ends_with("project:\nUse tabs."));
+        assert!(messages[1].content.contains("let a = 1;\n<CURSOR>"));
+    }
+
+    #[test]
+    fn test_chat_messages() {
+        let file = make_file("let a = 1;\n", (0, 0), (0, 0));
+        let conversation = vec![
+            ConversationMessage::new(MessageType::User, "What is a?".to_owned()),
+            ConversationMessage::new(MessageType::Bot, "A number.".to_owned()),
+            ConversationMessage::new(MessageType::User, "Why?".to_owned()),
+            ConversationMessage::empty_message(MessageType::Bot),
+        ];
+        let roles = chat_messages(&file, &ExplicitContext::default(), &conversation)
+            .into_iter()
+            .map(|m| m.role)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            roles,
+            [
+                Role::System,
+                Role::User,
+                Role::User,
+                Role::Assistant,
+                Role::User
+            ]
+        );
+    }
+
+    #[test]
+    fn test_message_text() {
+        let mut message = ConversationMessage::new(MessageType::User, "Why?".to_owned());
+        message.attached_code_chunks.push(CodeChunk {
+            relative_workspace_path: "src/lib.rs".to_owned(),
+            start_line: 9,
+            lines: vec!["fn a() {}".to_owned(), "fn b() {}".to_owned()],
+        });
+        assert_eq!(
+            message_text(&message),
+            "Why?\n\n`src/lib.rs` from line 10:\n```\nfn a() {}\nfn b() {}\n```"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! A decoder of [server-sent events](https://html.spec.whatwg.org/multipage/server-sent-events.html),
+//! which only cares about the `data` field.
+
+/// Decodes the data of the events from chunks of the response body.
+///
+/// Chunks may split lines or even UTF-8 sequences, so incomplete lines are
+/// buffered until the next chunk arrives.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Option<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk and returns the data of the events completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = vec![];
+        while let Some(index) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line = self.buffer.drain(..=index).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = self.process_line(line.trim_end_matches(['\n', '\r'])) {
+                events.push(data);
+            }
+        }
+        events
+    }
+
+    /// Returns the data of the last event if the stream ends without a
+    /// blank line.
+    pub fn finish(&mut self) -> Option<String> {
+        let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned();
+        self.process_line(line.trim_end_matches('\r'));
+        self.data.take()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<String> {
+        if line.is_empty() {
+            // A blank line dispatches the event.
+            return self.data.take();
+        }
+        // Other fields and comments are ignored.
+        let value = line.strip_prefix("data:")?;
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match &mut self.data {
+            Some(data) => {
+                data.push('\n');
+                data.push_str(value);
+            }
+            None => self.data = Some(value.to_owned()),
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_events() {
+        let mut decoder = SseDecoder::new();
+        assert_eq!(
+            decoder.push(b": comment\ndata: {\"a\":1}\n\ndata: [DONE]\n\n"),
+            vec!["{\"a\":1}", "[DONE]"]
+        );
+    }
+
+    #[test]
+    fn test_decode_split_chunks() {
+        let mut decoder = SseDecoder::new();
+        let text = "data: héllo\r\n\r\ndata: line1\ndata:line2\n\n".as_bytes();
+        // Split inside the multi-byte `é`.
+        let (first, second) = text.split_at(8);
+        assert!(decoder.push(first).is_empty());
+        assert_eq!(decoder.push(second), vec!["héllo", "line1\nline2"]);
+    }
+
+    #[test]
+    fn test_finish() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: last").is_empty());
+        assert_eq!(decoder.finish().as_deref(), Some("last"));
+        assert_eq!(decoder.finish(), None);
+    }
+}
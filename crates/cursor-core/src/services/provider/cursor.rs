@@ -0,0 +1,185 @@
+use std::pin::pin;
+
+use futures::{future::LocalBoxFuture, FutureExt, StreamExt};
+use node_bridge::{http_client::HttpMethod, prelude::*};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    auth::valid_account_token,
+    model_registry::ModelInfo,
+    request::{make_request, JsonSendable, API2_HOST},
+    services::{
+        chat::models::request_body::RequestBody as ChatRequestBody,
+        enveloped_message::{FilledPrompt, MessageContent},
+        generate::request_body::RequestBody as GenerateRequestBody,
+        stream::make_stream,
+    },
+};
+
+use super::{Completion, CompletionProvider, TextSink};
+
+/// The Connect client of `aiserver.v1.AiService` on the Cursor server.
+pub struct CursorProvider;
+
+/// A data frame of the streamed reply.
+#[derive(Debug, PartialEq, Eq)]
+enum Frame {
+    FilledPrompt(String),
+    /// A piece of the reply.
+    Text(String),
+}
+
+/// Parses a data frame, the frames of other kinds are skipped.
+fn parse_frame(data: &str) -> Option<Frame> {
+    if data.is_empty() {
+        return None;
+    }
+    if let Ok(prompt) = serde_json::from_str::<FilledPrompt>(data) {
+        return Some(Frame::FilledPrompt(prompt.text));
+    }
+    serde_json::from_str::<MessageContent>(data)
+        .ok()
+        .map(|MessageContent { text }| Frame::Text(text))
+}
+
+impl CursorProvider {
+    async fn stream<T>(path: &str, body: &T, sink: TextSink<'_>) -> Result<Completion, JsValue>
+    where
+        T: Serialize,
+    {
+        #[cfg(debug_assertions)]
+        console::log_str(&serde_json::to_string_pretty(body).unwrap());
+
+        let mut state = make_stream(path, body).await?;
+        let mut reply = Completion::default();
+        {
+            let mut data_stream = pin!(state.data_stream());
+            while let Some(chunk) = data_stream.next().await {
+                if chunk.is_end() {
+                    break;
+                }
+                let data = chunk
+                    .utf8_string()
+                    .map_err(|e| JsError::new(&e.to_string()))?;
+                match parse_frame(&data) {
+                    Some(Frame::FilledPrompt(prompt)) => {
+                        #[cfg(debug_assertions)]
+                        console::log_str(&format!("prompt: \n{prompt}"));
+                        reply.filled_prompt = Some(prompt);
+                    }
+                    Some(Frame::Text(text)) => {
+                        #[cfg(debug_assertions)]
+                        console::log_str(&format!("wrote: {text}"));
+                        sink(&text);
+                        reply.text.push_str(&text);
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        // Make sure the response is fully received without errors.
+        state.complete().await?;
+        Ok(reply)
+    }
+}
+
+impl CompletionProvider for CursorProvider {
+    fn stream_chat<'a>(
+        &'a self,
+        request: &'a ChatRequestBody,
+        sink: TextSink<'a>,
+    ) -> LocalBoxFuture<'a, Result<Completion, JsValue>> {
+        Self::stream("/aiserver.v1.AiService/StreamChat", request, sink).boxed_local()
+    }
+
+    fn stream_generate<'a>(
+        &'a self,
+        request: &'a GenerateRequestBody,
+        sink: TextSink<'a>,
+    ) -> LocalBoxFuture<'a, Result<String, JsValue>> {
+        Self::stream("/aiserver.v1.AiService/StreamGenerate", request, sink)
+            .map(|reply| reply.map(|reply| reply.text))
+            .boxed_local()
+    }
+
+    fn stream_edit<'a>(
+        &'a self,
+        request: &'a GenerateRequestBody,
+        sink: TextSink<'a>,
+    ) -> LocalBoxFuture<'a, Result<String, JsValue>> {
+        Self::stream("/aiserver.v1.AiService/StreamEdit", request, sink)
+            .map(|reply| reply.map(|reply| reply.text))
+            .boxed_local()
+    }
+
+    fn list_models(&self) -> LocalBoxFuture<'_, Result<Vec<ModelInfo>, JsValue>> {
+        async move {
+            Ok(fetch_available_models()
+                .await?
+                .into_iter()
+                .map(|name| ModelInfo::new(name, None))
+                .collect())
+        }
+        .boxed_local()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AvailableModelsResponse {
+    model_names: Vec<String>,
+}
+
+/// Asks the server which models the signed-in account can use.
+async fn fetch_available_models() -> Result<Vec<String>, JsValue> {
+    let token = valid_account_token()
+        .await
+        .ok_or_else(|| JsError::new("Not signed in"))?;
+    let mut response = make_request(
+        API2_HOST,
+        "/aiserver.v1.AiService/AvailableModels",
+        HttpMethod::Post,
+    )
+    .add_header("Authorization", &format!("Bearer {}", token.access_token))
+    .set_json_body(&json!({}))
+    .send()
+    .await?;
+    let status_code = response.status_code();
+    let data = response.text().await;
+    if status_code != 200 {
+        return Err(
+            js_sys::Error::new(&format!("Server returned status code {status_code}")).into(),
+        );
+    }
+    let response = serde_json::from_str::<AvailableModelsResponse>(&data).map_err(JsError::from)?;
+    Ok(response.model_names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_frame() {
+        assert_eq!(
+            parse_frame(r#"{"filledPrompt":"User: hi"}"#),
+            Some(Frame::FilledPrompt("User: hi".to_owned()))
+        );
+        assert_eq!(parse_frame(""), None);
+        assert_eq!(parse_frame(r#"{"debugInfo":{}}"#), None);
+
+        // Only the text of the frames makes up the reply.
+        let reply = [r#"{"text":"Hel"}"#, r#"{"text":"lo"}"#]
+            .into_iter()
+            .filter_map(parse_frame)
+            .map(|frame| match frame {
+                Frame::Text(text) => text,
+                frame => panic!("unexpected frame {frame:?}"),
+            })
+            .collect::<String>();
+        assert_eq!(reply, "Hello");
+    }
+}
@@ -0,0 +1,74 @@
+//! Backends that serve completions.
+//!
+//! Services build their requests once and hand them to the provider chosen
+//! by [`provider_for`], so adding a backend only takes a new implementation
+//! of [`CompletionProvider`].
+
+mod cursor;
+mod openai;
+
+use futures::future::LocalBoxFuture;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    model_configuration::{ModelConfiguration, Provider},
+    model_registry::ModelInfo,
+};
+
+use self::{cursor::CursorProvider, openai::OpenAiProvider};
+
+use super::{
+    chat::models::request_body::RequestBody as ChatRequestBody,
+    generate::request_body::RequestBody as GenerateRequestBody, openai::OpenAiClient,
+};
+
+/// Receives the pieces of the reply as they arrive.
+pub type TextSink<'a> = &'a mut dyn FnMut(&str);
+
+/// A finished reply.
+#[derive(Debug, Clone, Default)]
+pub struct Completion {
+    pub text: String,
+    /// The prompt the server filled the request into, if it reports it.
+    pub filled_prompt: Option<String>,
+}
+
+/// A backend that streams completions.
+///
+/// Each streaming method writes the reply to `sink` piece by piece, and
+/// returns the whole reply when it's finished.
+pub trait CompletionProvider {
+    /// Streams the reply to the last message of the conversation.
+    fn stream_chat<'a>(
+        &'a self,
+        request: &'a ChatRequestBody,
+        sink: TextSink<'a>,
+    ) -> LocalBoxFuture<'a, Result<Completion, JsValue>>;
+
+    /// Streams the code to insert at the cursor.
+    fn stream_generate<'a>(
+        &'a self,
+        request: &'a GenerateRequestBody,
+        sink: TextSink<'a>,
+    ) -> LocalBoxFuture<'a, Result<String, JsValue>>;
+
+    /// Streams the code to replace the selection.
+    fn stream_edit<'a>(
+        &'a self,
+        request: &'a GenerateRequestBody,
+        sink: TextSink<'a>,
+    ) -> LocalBoxFuture<'a, Result<String, JsValue>>;
+
+    /// Returns the models that can be used with this provider.
+    fn list_models(&self) -> LocalBoxFuture<'_, Result<Vec<ModelInfo>, JsValue>>;
+}
+
+/// Returns the provider selected in the configuration.
+pub fn provider_for(configuration: &ModelConfiguration) -> Box<dyn CompletionProvider> {
+    match configuration.provider() {
+        Provider::Cursor => Box::new(CursorProvider),
+        Provider::OpenAi | Provider::Local => Box::new(OpenAiProvider::new(
+            OpenAiClient::from_configuration(configuration),
+        )),
+    }
+}
@@ -0,0 +1,98 @@
+use futures::{future::LocalBoxFuture, FutureExt};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    model_registry::ModelInfo,
+    services::{
+        chat::models::request_body::RequestBody as ChatRequestBody,
+        generate::request_body::RequestBody as GenerateRequestBody,
+        openai::{fence::CodeFenceFilter, models::ChatMessage, prompt, OpenAiClient},
+        stream::models::model_details::ModelDetails,
+    },
+};
+
+use super::{Completion, CompletionProvider, TextSink};
+
+/// OpenAI-compatible APIs, with the prompts built locally.
+pub struct OpenAiProvider {
+    client: OpenAiClient,
+}
+
+impl OpenAiProvider {
+    pub fn new(client: OpenAiClient) -> Self {
+        Self { client }
+    }
+
+    /// Streams a reply which is expected to be code only.
+    async fn stream_code(
+        &self,
+        model: &ModelDetails,
+        messages: Vec<ChatMessage>,
+        sink: TextSink<'_>,
+    ) -> Result<String, JsValue> {
+        let mut filter = CodeFenceFilter::new();
+        let mut code = String::new();
+        self.client
+            .stream_chat(model, &messages, |text| {
+                let text = filter.push(text);
+                sink(&text);
+                code.push_str(&text);
+            })
+            .await?;
+        let rest = filter.finish();
+        sink(&rest);
+        code.push_str(&rest);
+        Ok(code)
+    }
+}
+
+impl CompletionProvider for OpenAiProvider {
+    fn stream_chat<'a>(
+        &'a self,
+        request: &'a ChatRequestBody,
+        sink: TextSink<'a>,
+    ) -> LocalBoxFuture<'a, Result<Completion, JsValue>> {
+        let messages = prompt::chat_messages(
+            &request.current_file,
+            &request.context,
+            &request.conversation,
+        );
+        async move {
+            let text = self
+                .client
+                .stream_chat(&request.model_details, &messages, sink)
+                .await?;
+            Ok(Completion {
+                text,
+                filled_prompt: None,
+            })
+        }
+        .boxed_local()
+    }
+
+    fn stream_generate<'a>(
+        &'a self,
+        request: &'a GenerateRequestBody,
+        sink: TextSink<'a>,
+    ) -> LocalBoxFuture<'a, Result<String, JsValue>> {
+        let messages =
+            prompt::generate_messages(&request.query, &request.current_file, &request.context);
+        self.stream_code(&request.model_details, messages, sink)
+            .boxed_local()
+    }
+
+    fn stream_edit<'a>(
+        &'a self,
+        request: &'a GenerateRequestBody,
+        sink: TextSink<'a>,
+    ) -> LocalBoxFuture<'a, Result<String, JsValue>> {
+        let messages =
+            prompt::edit_messages(&request.query, &request.current_file, &request.context);
+        self.stream_code(&request.model_details, messages, sink)
+            .boxed_local()
+    }
+
+    fn list_models(&self) -> LocalBoxFuture<'_, Result<Vec<ModelInfo>, JsValue>> {
+        self.client.list_models().boxed_local()
+    }
+}
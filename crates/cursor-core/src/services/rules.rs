@@ -0,0 +1,94 @@
+//! Project-specific instructions kept in a rules file of the workspace.
+//!
+//! The rules are sent as the explicit context of chat, generate and edit
+//! requests. The file is `.cursorrules` in the workspace root unless another
+//! one is configured.
+
+use node_bridge::{bindings::fs, prelude::*};
+
+use crate::context::get_extension_context;
+
+use super::{stream::models::explicit_context::ExplicitContext, workspace_path::is_absolute};
+
+const DEFAULT_RULES_FILE: &str = ".cursorrules";
+/// Rules longer than this are cut, so they can't crowd out the code.
+const MAX_RULES_CHARS: usize = 8 * 1024;
+
+/// Returns the path of the rules file, relative paths are resolved against
+/// the workspace root.
+fn rules_path(workspace_directory: Option<&str>, configured: Option<&str>) -> Option<String> {
+    let path = configured
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .unwrap_or(DEFAULT_RULES_FILE);
+    if is_absolute(path) {
+        return Some(path.to_owned());
+    }
+    let root = workspace_directory.filter(|root| !root.is_empty())?;
+    Some(format!("{}/{path}", root.trim_end_matches(['/', '\\'])))
+}
+
+fn truncate_rules(mut rules: String) -> String {
+    if let Some((index, _)) = rules.char_indices().nth(MAX_RULES_CHARS) {
+        rules.truncate(index);
+    }
+    rules.trim().to_owned()
+}
+
+/// Loads the rules of the workspace, empty if there is no rules file.
+pub async fn load_rules(workspace_directory: Option<&str>) -> ExplicitContext {
+    let configured = get_extension_context().model_configuration().rules_file();
+    let Some(path) = rules_path(workspace_directory, configured.as_deref()) else {
+        return ExplicitContext::default();
+    };
+    let rules = match fs::read_file(&path, "utf8").await {
+        Ok(rules) => truncate_rules(rules.as_string().unwrap_or_default()),
+        Err(_) => {
+            // Only a configured file is expected to exist.
+            if configured.is_some() {
+                console::warn_str(&format!("Failed to read the rules file {path}"));
+            }
+            return ExplicitContext::default();
+        }
+    };
+
+    #[cfg(debug_assertions)]
+    console::log_str(&format!(
+        "applied rules from {path} ({} chars)",
+        rules.chars().count()
+    ));
+    ExplicitContext::new(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_path() {
+        assert_eq!(
+            rules_path(Some("/work/app"), None).as_deref(),
+            Some("/work/app/.cursorrules")
+        );
+        assert_eq!(
+            rules_path(Some("/work/app/"), Some("docs/rules.md")).as_deref(),
+            Some("/work/app/docs/rules.md")
+        );
+        assert_eq!(
+            rules_path(None, Some("C:\\rules.md")).as_deref(),
+            Some("C:\\rules.md")
+        );
+        assert_eq!(rules_path(None, Some(" ")), None);
+    }
+
+    #[test]
+    fn test_truncate_rules() {
+        assert_eq!(truncate_rules("  use tabs\n".to_owned()), "use tabs");
+        assert_eq!(
+            truncate_rules("é".repeat(MAX_RULES_CHARS + 1))
+                .chars()
+                .count(),
+            MAX_RULES_CHARS
+        );
+    }
+}
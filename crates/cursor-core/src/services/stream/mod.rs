@@ -1,5 +1,7 @@
 pub mod models;
 
+use std::future::Future;
+
 use futures::{Stream, StreamExt};
 use js_sys::Uint8Array;
 use node_bridge::http_client::{HttpMethod, HttpResponse};
@@ -8,12 +10,12 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
 
 use crate::{
-    auth::account_token,
+    auth::{refresh_account_token, token::Token, valid_account_token},
     context::get_extension_context,
     request::{make_request, API2_HOST},
 };
 
-use super::enveloped_message::EnvelopedMessage;
+use super::{account::invalidate_account_info, enveloped_message::EnvelopedMessage};
 
 const SIGN_IN_ITEM: &str = "Sign In / Sign Up";
 const CONFIGURE_API_KEY_ITEM: &str = "Configure API Key";
@@ -49,37 +51,16 @@ pub async fn make_stream<T>(path: &str, body: &T) -> Result<StreamResponseState,
 where
     T: Serialize,
 {
-    let mut request = make_request(API2_HOST, path, HttpMethod::Post)
-        .add_header("content-type", "application/connect+json");
-
-    let context = get_extension_context();
-    if let Some(token) = account_token() {
-        request = request.add_header("Authorization", &format!("Bearer {}", token.access_token));
-    } else if context.model_configuration().api_key().is_none() {
-        spawn_local(async move {
-            if let Some(pick) = context
-                .show_information_message(
-                    "You have to sign in / sign up or configure API key to use Cursor AI features",
-                    [SIGN_IN_ITEM, CONFIGURE_API_KEY_ITEM]
-                        .into_iter()
-                        .map(JsValue::from)
-                        .collect(),
-                )
-                .await
-                .as_string()
-            {
-                context
-                    .execute_command0(&format!(
-                        "aicursor.{}",
-                        if pick == SIGN_IN_ITEM {
-                            "signInUp"
-                        } else {
-                            "configureApiKey"
-                        }
-                    ))
-                    .await;
-            }
-        });
+    let token = valid_account_token().await;
+    if token.is_none()
+        && get_extension_context()
+            .model_configuration()
+            .api_key()
+            .is_none()
+    {
+        prompt_sign_in(
+            "You have to sign in / sign up or configure API key to use Cursor AI features",
+        );
         return Err(JsError::new("No API key or account token").into());
     }
 
@@ -88,19 +69,174 @@ where
     // The data will always end with an empty data block flagged as 2.
     let body = [chunk, EnvelopedMessage::end()]
         .into_iter()
-        .map(|d| d.encode())
-        .reduce(|a, b| {
-            let mut result = a;
-            result.extend_from_slice(&b);
-            result
-        });
-    let response = request.set_body(body).send().await?;
-    if response.status_code() != 200 {
-        return Err(js_sys::Error::new(&format!(
-            "Server returned status code {}",
-            response.status_code()
-        ))
-        .into());
+        .flat_map(|d| d.encode())
+        .collect::<Vec<_>>();
+
+    let body = &body;
+    let response = send_with_refresh(
+        token,
+        |token| async move { send_stream_request(path, body, token.as_ref()).await },
+        HttpResponse::status_code,
+        refresh_account_token,
+    )
+    .await?;
+
+    match response.status_code() {
+        200 => {}
+        429 => {
+            // The cached usage is stale now.
+            invalidate_account_info();
+            return Err(js_sys::Error::new(
+                "You have run out of requests for this period, \
+                please upgrade your plan or configure an API key",
+            )
+            .into());
+        }
+        status_code => {
+            return Err(
+                js_sys::Error::new(&format!("Server returned status code {status_code}")).into(),
+            )
+        }
     }
     Ok(response.into())
 }
+
+/// Sends a request with the token, if the token is rejected it's refreshed
+/// and the request is replayed once with the new token.
+async fn send_with_refresh<T, F, R>(
+    token: Option<Token>,
+    send: impl Fn(Option<Token>) -> F,
+    status_code: impl Fn(&T) -> u16,
+    refresh: impl FnOnce() -> R,
+) -> Result<T, JsValue>
+where
+    F: Future<Output = Result<T, JsValue>>,
+    R: Future<Output = Result<Token, JsValue>>,
+{
+    let signed_in = token.is_some();
+    let response = send(token).await?;
+    if status_code(&response) != 401 || !signed_in {
+        return Ok(response);
+    }
+
+    // The access token has expired or been revoked.
+    drop(response);
+    let token = match refresh().await {
+        Ok(token) => token,
+        Err(err) => {
+            prompt_sign_in("Your session has expired, please sign in again");
+            return Err(err);
+        }
+    };
+    send(Some(token)).await
+}
+
+async fn send_stream_request(
+    path: &str,
+    body: &[u8],
+    token: Option<&Token>,
+) -> Result<HttpResponse, JsValue> {
+    let mut request = make_request(API2_HOST, path, HttpMethod::Post)
+        .add_header("content-type", "application/connect+json");
+    if let Some(token) = token {
+        request = request.add_header("Authorization", &format!("Bearer {}", token.access_token));
+    }
+    request.set_body(Some(body)).send().await
+}
+
+fn prompt_sign_in(message: &str) {
+    let context = get_extension_context();
+    let message = message.to_owned();
+    spawn_local(async move {
+        if let Some(pick) = context
+            .show_information_message(
+                &message,
+                [SIGN_IN_ITEM, CONFIGURE_API_KEY_ITEM]
+                    .into_iter()
+                    .map(JsValue::from)
+                    .collect(),
+            )
+            .await
+            .as_string()
+        {
+            context
+                .execute_command0(&format!(
+                    "aicursor.{}",
+                    if pick == SIGN_IN_ITEM {
+                        "signInUp"
+                    } else {
+                        "configureApiKey"
+                    }
+                ))
+                .await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use futures::{executor::block_on, future};
+
+    use super::*;
+
+    fn make_token(access_token: &str) -> Token {
+        Token {
+            access_token: access_token.to_owned(),
+            refresh_token: "refresh".to_owned(),
+            challenge: "challenge".to_owned(),
+            expires_at: None,
+        }
+    }
+
+    /// Sends with the given status codes in turn, and returns the access
+    /// tokens sent along with the number of refreshes.
+    fn send(token: Option<Token>, status_codes: &[u16]) -> (u16, Vec<Option<String>>, usize) {
+        let sent = RefCell::new(vec![]);
+        let refreshes = Cell::new(0);
+        let status_code = block_on(send_with_refresh(
+            token,
+            |token| {
+                let mut sent = sent.borrow_mut();
+                let status_code = status_codes[sent.len()];
+                sent.push(token.map(|token| token.access_token));
+                future::ok(status_code)
+            },
+            |status_code| *status_code,
+            || {
+                refreshes.set(refreshes.get() + 1);
+                future::ok(make_token("refreshed"))
+            },
+        ))
+        .unwrap();
+        (status_code, sent.into_inner(), refreshes.get())
+    }
+
+    #[test]
+    fn test_send_with_refresh() {
+        let (status_code, sent, refreshes) = send(Some(make_token("stale")), &[401, 200]);
+        assert_eq!(status_code, 200);
+        assert_eq!(
+            sent,
+            [Some("stale".to_owned()), Some("refreshed".to_owned())]
+        );
+        assert_eq!(refreshes, 1);
+
+        // The request is replayed only once.
+        let (status_code, sent, refreshes) = send(Some(make_token("stale")), &[401, 401]);
+        assert_eq!(status_code, 401);
+        assert_eq!(sent.len(), 2);
+        assert_eq!(refreshes, 1);
+
+        // Requests with an API key have no token to refresh.
+        let (status_code, sent, refreshes) = send(None, &[401]);
+        assert_eq!(status_code, 401);
+        assert_eq!(sent, [None]);
+        assert_eq!(refreshes, 0);
+
+        let (_, sent, refreshes) = send(Some(make_token("valid")), &[200]);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(refreshes, 0);
+    }
+}
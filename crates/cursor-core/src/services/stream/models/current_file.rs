@@ -1,6 +1,9 @@
 use serde::Serialize;
 
-use crate::{Position as IPosition, SelectionRange};
+use crate::{
+    services::budget::{count_tokens, window_around},
+    Position as IPosition, SelectionRange,
+};
 
 #[derive(Debug, Clone, Serialize, PartialEq, Eq)]
 pub struct Selection {
@@ -51,3 +54,335 @@ pub struct CurrentFile {
     #[serde(rename = "cursorPosition")]
     pub cursor: Position,
 }
+
+/// Replaces each run of lines left out of the context.
+const ELISION_MARKER: &str = "... lines omitted ...";
+/// Left for the elision markers, as a fraction of the budget.
+const MARKER_BUDGET_DIVISOR: usize = 10;
+
+const IMPORT_PREFIXES: [&str; 10] = [
+    "use ",
+    "import ",
+    "from ",
+    "#include",
+    "#import",
+    "using ",
+    "package ",
+    "extern crate ",
+    "mod ",
+    "require ",
+];
+const SIGNATURE_MODIFIERS: [&str; 12] = [
+    "pub(crate) ",
+    "pub ",
+    "export default ",
+    "export ",
+    "async ",
+    "static ",
+    "public ",
+    "private ",
+    "protected ",
+    "abstract ",
+    "unsafe ",
+    "override ",
+];
+const SIGNATURE_KEYWORDS: [&str; 13] = [
+    "fn ",
+    "def ",
+    "class ",
+    "struct ",
+    "enum ",
+    "trait ",
+    "impl ",
+    "impl<",
+    "interface ",
+    "function ",
+    "func ",
+    "type ",
+    "module ",
+];
+const CLOSING_PREFIXES: [&str; 4] = ["}", "]", ")", "end"];
+
+impl CurrentFile {
+    /// Keeps what matters the most around the selection and the cursor to fit
+    /// in `budget` tokens, and elides the rest with markers.
+    ///
+    /// In the order of priority, it keeps the selection and the cursor, the
+    /// headers of the scopes enclosing them, the imports, the signatures of
+    /// the other symbols, and the lines around them. The coordinates are
+    /// adjusted to the elided content.
+    pub fn fit_to_budget(&mut self, budget: usize) {
+        let lines = self.content.split_inclusive('\n').collect::<Vec<_>>();
+        let costs = lines
+            .iter()
+            .map(|line| count_tokens(line))
+            .collect::<Vec<_>>();
+        if costs.iter().sum::<usize>() <= budget {
+            return;
+        }
+
+        let first = self.selection.start.line.min(self.cursor.line);
+        let last = self.selection.end.line.max(self.cursor.line);
+        let budget = budget - budget / MARKER_BUDGET_DIVISOR;
+        let keep = select_lines(&lines, &costs, first, last, budget);
+
+        let mut content = String::new();
+        let mut line_map = vec![0; lines.len()];
+        let mut output_line = 0;
+        let mut index = 0;
+        while index < lines.len() {
+            if keep[index] {
+                content.push_str(lines[index]);
+                line_map[index] = output_line;
+                output_line += 1;
+                index += 1;
+                continue;
+            }
+            let elided = keep[index..].iter().take_while(|keep| !**keep).count();
+            let indent = lines
+                .get(index + elided)
+                .map(|line| &line[..line.len() - line.trim_start().len()])
+                .unwrap_or_default();
+            content.push_str(&format!("{indent}{ELISION_MARKER}\n"));
+            output_line += 1;
+            index += elided;
+        }
+
+        self.content = content;
+        for position in [
+            &mut self.selection.start,
+            &mut self.selection.end,
+            &mut self.cursor,
+        ] {
+            // The line after a trailing newline is past the end of `lines`.
+            position.line = line_map.get(position.line).copied().unwrap_or(output_line);
+        }
+    }
+}
+
+/// Returns which lines to keep, see [`CurrentFile::fit_to_budget`].
+fn select_lines(
+    lines: &[&str],
+    costs: &[usize],
+    first: usize,
+    last: usize,
+    budget: usize,
+) -> Vec<bool> {
+    let mut keep = vec![false; lines.len()];
+    if lines.is_empty() {
+        return keep;
+    }
+    let last = last.min(lines.len() - 1);
+    let first = first.min(last);
+
+    // The selection is kept even if it doesn't fit.
+    keep[first..=last].iter_mut().for_each(|keep| *keep = true);
+    let mut used = costs[first..=last].iter().sum::<usize>();
+    let mut try_keep = |line: usize, keep: &mut Vec<bool>| {
+        if !keep[line] && used + costs[line] <= budget {
+            keep[line] = true;
+            used += costs[line];
+        }
+    };
+
+    for line in enclosing_scopes(lines, first, last) {
+        try_keep(line, &mut keep);
+    }
+    for line in (0..lines.len()).filter(|&line| is_import(lines[line])) {
+        try_keep(line, &mut keep);
+    }
+    let mut signatures = (0..lines.len())
+        .filter(|&line| is_signature(lines[line]))
+        .collect::<Vec<_>>();
+    // The closer to the selection, the more relevant.
+    signatures.sort_by_key(|&line| line.abs_diff(first).min(line.abs_diff(last)));
+    for line in signatures {
+        try_keep(line, &mut keep);
+    }
+
+    // Spend the rest on the lines around the selection, the kept lines are
+    // already paid for.
+    let remaining_costs = costs
+        .iter()
+        .zip(&keep)
+        .map(|(cost, keep)| if *keep { 0 } else { *cost })
+        .collect::<Vec<_>>();
+    let (start, end) = window_around(&remaining_costs, first, last, budget - used.min(budget));
+    keep[start..=end].iter_mut().for_each(|keep| *keep = true);
+    keep
+}
+
+/// Returns the headers of the scopes enclosing the lines from `first` to
+/// `last`, along with their closing lines, judging by the indentation.
+fn enclosing_scopes(lines: &[&str], first: usize, last: usize) -> Vec<usize> {
+    let mut scopes = vec![];
+    let mut scope_indent = lines[first..=last]
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indent_of(line))
+        .min()
+        .unwrap_or(usize::MAX);
+    for line in (0..first).rev() {
+        if scope_indent == 0 {
+            break;
+        }
+        let text = lines[line];
+        if text.trim().is_empty() || indent_of(text) >= scope_indent {
+            continue;
+        }
+        scope_indent = indent_of(text);
+        scopes.push(line);
+
+        let closing = (last + 1..lines.len())
+            .find(|&line| !lines[line].trim().is_empty() && indent_of(lines[line]) <= scope_indent)
+            .filter(|&line| {
+                let text = lines[line].trim_start();
+                CLOSING_PREFIXES
+                    .iter()
+                    .any(|prefix| text.starts_with(prefix))
+            });
+        scopes.extend(closing);
+    }
+    scopes
+}
+
+fn indent_of(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+fn is_import(line: &str) -> bool {
+    // Imports are only recognized at the top level, so that statements like
+    // `from` in SQL strings are not mistaken for them.
+    indent_of(line) == 0
+        && IMPORT_PREFIXES
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+}
+
+fn is_signature(line: &str) -> bool {
+    let mut text = line.trim_start();
+    while let Some(rest) = SIGNATURE_MODIFIERS
+        .iter()
+        .find_map(|modifier| text.strip_prefix(modifier))
+    {
+        text = rest;
+    }
+    SIGNATURE_KEYWORDS
+        .iter()
+        .any(|keyword| text.starts_with(keyword))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    #[test]
+    fn test_fit_to_budget() {
+        let mut content = "use std::io;\n\nstruct Foo;\n\nimpl Foo {\n".to_owned();
+        content.extend((0..200).map(|i| format!("    const A{i}: u32 = {i};\n")));
+        content.push_str("    fn bar(&self) {\n        let a = 1;\n        let b = 2;\n    }\n");
+        content.extend((0..200).map(|i| format!("    const B{i}: u32 = {i};\n")));
+        content.push_str("}\n");
+        let mut file = CurrentFile {
+            content: content.clone(),
+            language_id: "rust".to_owned(),
+            relative_workspace_path: "src/main.rs".to_owned(),
+            selection: Selection {
+                start: position(206, 8),
+                end: position(207, 18),
+            },
+            cursor: position(207, 18),
+        };
+        file.fit_to_budget(100);
+
+        let lines = file.content.lines().collect::<Vec<_>>();
+        assert!(lines.len() < 60);
+        assert_eq!(lines[0], "use std::io;");
+        assert!(lines.contains(&"struct Foo;"));
+        assert!(lines.contains(&"impl Foo {"));
+        assert!(lines.contains(&"    fn bar(&self) {"));
+        assert!(lines.contains(&"    ... lines omitted ..."));
+        assert_eq!(lines.last(), Some(&"}"));
+
+        assert_eq!(lines[file.selection.start.line], "        let a = 1;");
+        assert_eq!(lines[file.selection.end.line], "        let b = 2;");
+        assert_eq!(file.selection.start.column, 8);
+        assert_eq!(file.cursor, file.selection.end);
+
+        // The cursor at the end of the file, after the trailing newline.
+        let end = position(content.lines().count(), 0);
+        let mut file = CurrentFile {
+            content,
+            language_id: "rust".to_owned(),
+            relative_workspace_path: "src/main.rs".to_owned(),
+            selection: Selection {
+                start: end.clone(),
+                end: end.clone(),
+            },
+            cursor: end,
+        };
+        file.fit_to_budget(100);
+        assert!(file.content.ends_with("}\n"));
+        assert_eq!(file.cursor.line, file.content.lines().count());
+        assert_eq!(file.selection.start, file.cursor);
+    }
+
+    #[test]
+    fn test_fit_to_budget_small_file() {
+        let content = "fn main() {}\n".to_owned();
+        let mut file = CurrentFile {
+            content: content.clone(),
+            language_id: "rust".to_owned(),
+            relative_workspace_path: "src/main.rs".to_owned(),
+            selection: Selection {
+                start: position(0, 0),
+                end: position(0, 0),
+            },
+            cursor: position(0, 0),
+        };
+        file.fit_to_budget(100);
+        assert_eq!(file.content, content);
+    }
+
+    #[test]
+    fn test_enclosing_scopes() {
+        let lines = [
+            "class A:\n",
+            "    x = 1\n",
+            "    def f(self):\n",
+            "        return 1\n",
+            "\n",
+            "    def g(self):\n",
+            "        return 2\n",
+        ];
+        assert_eq!(enclosing_scopes(&lines, 6, 6), vec![5, 0]);
+        assert_eq!(enclosing_scopes(&lines, 0, 0), Vec::<usize>::new());
+
+        let lines = [
+            "impl A {\n",
+            "    fn f() {\n",
+            "        1\n",
+            "    }\n",
+            "}\n",
+        ];
+        assert_eq!(enclosing_scopes(&lines, 2, 2), vec![1, 3, 0, 4]);
+    }
+
+    #[test]
+    fn test_classify_lines() {
+        assert!(is_import("use std::io;"));
+        assert!(is_import("import os"));
+        assert!(!is_import("    from x"));
+        assert!(is_signature("pub async fn run() {"));
+        assert!(is_signature("export default function App() {"));
+        assert!(is_signature("    def f(self):"));
+        assert!(!is_signature("let a = 1;"));
+    }
+}
@@ -1,4 +1,24 @@
 use serde::Serialize;
 
-#[derive(Debug, Clone, Copy, Serialize)]
-pub struct ExplicitContext {}
+use crate::services::budget::count_tokens;
+
+/// Instructions of the project the model should follow.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExplicitContext {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub context: String,
+}
+
+impl ExplicitContext {
+    pub fn new(context: String) -> Self {
+        Self { context }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.context.is_empty()
+    }
+
+    pub fn token_count(&self) -> usize {
+        count_tokens(&self.context)
+    }
+}
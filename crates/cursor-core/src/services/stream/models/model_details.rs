@@ -1,5 +1,7 @@
 use serde::Serialize;
 
+use crate::model_configuration::ModelSelection;
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ModelDetails {
@@ -11,4 +13,54 @@ pub struct ModelDetails {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub api_key: Option<String>,
+
+    /// The generation parameters are only used by the OpenAI-compatible
+    /// providers, the Cursor server is not known to accept them.
+    #[serde(skip)]
+    pub temperature: Option<f64>,
+
+    #[serde(skip)]
+    pub max_tokens: Option<u32>,
+
+    #[serde(skip)]
+    pub stop: Vec<String>,
+}
+
+impl ModelDetails {
+    pub fn new(selection: ModelSelection, api_key: Option<String>) -> Self {
+        let parameters = selection.parameters;
+        Self {
+            name: selection.name,
+            ghost_mode: true,
+            api_key,
+            temperature: parameters.temperature,
+            max_tokens: parameters.max_tokens,
+            stop: parameters.stop,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model_configuration::GenerationParameters;
+
+    use super::*;
+
+    #[test]
+    fn test_serialize_without_parameters() {
+        let selection = ModelSelection {
+            name: "gpt-4".to_owned(),
+            parameters: GenerationParameters {
+                temperature: Some(0.2),
+                max_tokens: Some(1024),
+                stop: vec!["\n\n".to_owned()],
+            },
+        };
+        let details = ModelDetails::new(selection, None);
+        assert_eq!(details.max_tokens, Some(1024));
+        assert_eq!(
+            serde_json::to_value(&details).unwrap(),
+            serde_json::json!({ "modelName": "gpt-4", "enableGhostMode": true })
+        );
+    }
 }
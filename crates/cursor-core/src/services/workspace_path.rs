@@ -0,0 +1,138 @@
+//! Paths of files as sent to the backends, relative to the workspace folder
+//! so the absolute paths of the user's machine are never sent.
+
+/// Returns whether the path is absolute, on either Unix or Windows.
+pub fn is_absolute(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with('/')
+        || path.starts_with('\\')
+        || (bytes.len() > 2 && bytes[1] == b':' && matches!(bytes[2], b'/' | b'\\'))
+}
+
+fn is_windows_path(path: &str) -> bool {
+    path.contains('\\') || (path.len() > 1 && path.as_bytes()[1] == b':')
+}
+
+/// Splits the path into its components, with either separator.
+fn components(path: &str) -> Vec<&str> {
+    path.split(['/', '\\'])
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect()
+}
+
+/// Returns the components of `path` under `root`, `None` if it's outside.
+fn strip_root<'a>(path: &'a str, root: &str) -> Option<Vec<&'a str>> {
+    let path_components = components(path);
+    let root_components = components(root);
+    if root_components.len() > path_components.len() {
+        return None;
+    }
+    // Paths of Windows are case-insensitive.
+    let ignore_case = is_windows_path(path) || is_windows_path(root);
+    let matches = root_components.iter().zip(&path_components).all(|(a, b)| {
+        if ignore_case {
+            a.eq_ignore_ascii_case(b)
+        } else {
+            a == b
+        }
+    });
+    matches.then(|| path_components[root_components.len()..].to_vec())
+}
+
+/// Returns the path of the file relative to the workspace folder it belongs
+/// to, with `/` as the separator.
+///
+/// `file_root` is the workspace folder of the file, and `request_root` the
+/// one of the document the request is made from. In multi-root workspaces
+/// the files of other folders are prefixed with the name of their folder.
+/// Only the name is kept of files outside any workspace folder.
+pub fn relative_workspace_path(
+    file_path: &str,
+    file_root: Option<&str>,
+    request_root: Option<&str>,
+) -> String {
+    let file_root = file_root.filter(|root| !root.is_empty());
+    let relative = file_root.and_then(|root| {
+        let mut components = strip_root(file_path, root)?;
+        let same_root = request_root.is_some_and(|request_root| {
+            strip_root(root, request_root).is_some_and(|rest| rest.is_empty())
+        });
+        if !same_root {
+            if let Some(name) = components_last(root) {
+                components.insert(0, name);
+            }
+        }
+        Some(components.join("/"))
+    });
+    relative
+        .filter(|relative| !relative.is_empty())
+        .or_else(|| components_last(file_path).map(str::to_owned))
+        .unwrap_or_default()
+}
+
+/// Returns the name of the workspace folder, which is sent in place of its
+/// absolute path.
+pub fn workspace_folder_name(root: Option<&str>) -> String {
+    root.and_then(components_last)
+        .unwrap_or_default()
+        .to_owned()
+}
+
+fn components_last(path: &str) -> Option<&str> {
+    components(path).last().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_workspace_path() {
+        assert_eq!(
+            relative_workspace_path(
+                "/home/user/app/src/main.rs",
+                Some("/home/user/app"),
+                Some("/home/user/app/")
+            ),
+            "src/main.rs"
+        );
+        assert_eq!(
+            relative_workspace_path(
+                "c:\\Users\\user\\App\\src\\main.rs",
+                Some("C:\\Users\\user\\app"),
+                Some("C:\\Users\\user\\app")
+            ),
+            "src/main.rs"
+        );
+        // Another folder of a multi-root workspace.
+        assert_eq!(
+            relative_workspace_path(
+                "/home/user/lib/src/lib.rs",
+                Some("/home/user/lib"),
+                Some("/home/user/app")
+            ),
+            "lib/src/lib.rs"
+        );
+        // Outside any workspace folder.
+        assert_eq!(
+            relative_workspace_path("/home/user/notes.md", None, Some("/home/user/app")),
+            "notes.md"
+        );
+        assert_eq!(
+            relative_workspace_path(
+                "/home/user/application/main.rs",
+                Some("/home/user/app"),
+                None
+            ),
+            "main.rs"
+        );
+    }
+
+    #[test]
+    fn test_workspace_folder_name() {
+        assert_eq!(workspace_folder_name(Some("/home/user/app/")), "app");
+        assert_eq!(workspace_folder_name(Some("C:\\Users\\user\\app")), "app");
+        assert_eq!(workspace_folder_name(Some("/")), "");
+        assert_eq!(workspace_folder_name(None), "");
+    }
+}
@@ -0,0 +1,8 @@
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(module = "node:fs/promises")]
+extern "C" {
+    /// Reads the whole file as a string in the given encoding.
+    #[wasm_bindgen(js_name = readFile, catch)]
+    pub async fn read_file(path: &str, encoding: &str) -> Result<JsValue, JsValue>;
+}
@@ -0,0 +1,55 @@
+use wasm_bindgen::prelude::*;
+
+use super::https::ClientRequest;
+
+#[wasm_bindgen(module = "node:http")]
+extern "C" {
+    /// Same as `request` from `node:https`, but for plain `http:` URLs.
+    #[wasm_bindgen]
+    pub fn request(url: &str, options: JsValue) -> ClientRequest;
+}
+
+#[wasm_bindgen(module = "node:http")]
+extern "C" {
+    pub type Server;
+
+    #[wasm_bindgen(js_name = createServer)]
+    pub fn create_server() -> Server;
+
+    #[wasm_bindgen(method)]
+    pub fn listen(this: &Server, port: u16, host: &str);
+
+    #[wasm_bindgen(method)]
+    pub fn address(this: &Server) -> JsValue;
+
+    #[wasm_bindgen(method)]
+    pub fn close(this: &Server);
+
+    #[wasm_bindgen(method)]
+    pub fn on(this: &Server, event: &str, listener: JsValue);
+}
+
+#[wasm_bindgen(module = "node:http")]
+extern "C" {
+    pub type IncomingMessage;
+
+    #[wasm_bindgen(method, getter)]
+    pub fn url(this: &IncomingMessage) -> String;
+
+    #[wasm_bindgen(method, getter)]
+    pub fn method(this: &IncomingMessage) -> String;
+}
+
+#[wasm_bindgen(module = "node:http")]
+extern "C" {
+    pub type ServerResponse;
+
+    #[wasm_bindgen(method, js_name = setHeader)]
+    pub fn set_header(this: &ServerResponse, name: &str, value: &str);
+
+    #[wasm_bindgen(method, setter, js_name = statusCode)]
+    pub fn set_status_code(this: &ServerResponse, status_code: u16);
+
+    #[wasm_bindgen(method)]
+    pub fn end(this: &ServerResponse, data: &str);
+}
@@ -1,6 +1,8 @@
 pub mod abort_signal;
 pub mod buffer;
 pub mod console;
+pub mod fs;
+pub mod http;
 pub mod https;
 
 pub use abort_signal::AbortSignal;
@@ -9,7 +9,7 @@ use futures::StreamExt;
 use js_sys::{Object as JsObject, Reflect};
 use wasm_bindgen::prelude::*;
 
-use crate::bindings::https::*;
+use crate::bindings::{http, https::*};
 use crate::futures::{AsyncIter, Defer};
 use crate::prelude::*;
 use crate::{closure, closure_once};
@@ -44,11 +44,10 @@ impl std::fmt::Display for HttpMethod {
     }
 }
 
-
 /// An HTTP request.
 ///
 /// When performing the request, it uses [`request`] from Node.js as
-/// the underlying HTTP client.
+/// the underlying HTTP client, `node:http` is used for `http:` URLs.
 #[derive(Clone, Debug)]
 pub struct HttpRequest {
     url: String,
@@ -90,6 +89,26 @@ impl HttpRequest {
         self
     }
 
+    /// Returns the URL.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the method.
+    pub fn method(&self) -> HttpMethod {
+        self.method
+    }
+
+    /// Returns the value of a header.
+    pub fn header(&self, header_field: &str) -> Option<&str> {
+        self.headers.get(header_field).map(String::as_str)
+    }
+
+    /// Returns the request body.
+    pub fn body(&self) -> Option<&[u8]> {
+        self.body.as_deref()
+    }
+
     /// Sends the request.
     ///
     /// This is an asynchronous method which blocks the caller before the
@@ -110,7 +129,12 @@ impl HttpRequest {
         }
         Reflect::set(&options, &"headers".into(), &headers_obj)?;
 
-        let req = request(&self.url, options.into());
+        // `node:https` refuses `http:` URLs, which are used by local servers.
+        let req = if self.url.starts_with("http:") {
+            http::request(&self.url, options.into())
+        } else {
+            request(&self.url, options.into())
+        };
 
         let defer_resp = Defer::new();
         let defer_resp_clone = defer_resp.clone();
@@ -0,0 +1,116 @@
+use std::future::IntoFuture;
+
+use futures::future::{select, Either};
+use js_sys::Reflect;
+use wasm_bindgen::prelude::*;
+
+use crate::bindings::http::*;
+use crate::futures::{AsyncIter, Defer};
+use crate::{closure, closure_once};
+
+/// A minimal HTTP server.
+///
+/// It uses [`createServer`] from Node.js as the underlying server, and is
+/// intended for short-lived local endpoints, such as receiving OAuth
+/// redirects. The server is closed when dropped.
+pub struct HttpServer {
+    server: Server,
+    requests: AsyncIter<HttpServerRequest>,
+}
+
+impl HttpServer {
+    /// Starts listening on the given host and port, `0` means a random
+    /// port chosen by the OS.
+    ///
+    /// ## Errors
+    ///
+    /// This method returns [`Result::Err(JsValue)`] when the server cannot
+    /// be bound, e.g. the port is already in use.
+    pub async fn bind(host: &str, port: u16) -> Result<Self, JsValue> {
+        let server = create_server();
+
+        let requests = AsyncIter::new();
+        let mut requests_sender = requests.sender();
+        server.on(
+            "request",
+            closure!(|req: IncomingMessage, resp: ServerResponse| {
+                requests_sender.send(Some(HttpServerRequest { req, resp }));
+            })
+            .into_js_value(),
+        );
+
+        let defer_listening = Defer::new();
+        let defer_listening_clone = defer_listening.clone();
+        server.on(
+            "listening",
+            closure_once!(|| {
+                defer_listening_clone.resolve(JsValue::UNDEFINED);
+            })
+            .into_js_value(),
+        );
+
+        let defer_err = Defer::new();
+        let defer_err_clone = defer_err.clone();
+        server.on(
+            "error",
+            closure!(|err: JsValue| {
+                defer_err_clone.resolve(err);
+            })
+            .into_js_value(),
+        );
+
+        server.listen(port, host);
+        match select(defer_listening.into_future(), defer_err.into_future()).await {
+            Either::Left(_) => Ok(Self { server, requests }),
+            Either::Right((Ok(err), _)) => Err(err),
+            _ => unreachable!("Impossible code path"),
+        }
+    }
+
+    /// Returns the port the server is listening on, or `None` if the
+    /// address is not available.
+    pub fn port(&self) -> Option<u16> {
+        Reflect::get(&self.server.address(), &"port".into())
+            .ok()
+            .and_then(|port| port.as_f64())
+            .map(|port| port as u16)
+            .filter(|port| *port != 0)
+    }
+
+    /// Returns an [`AsyncIter<HttpServerRequest>`] of the incoming requests.
+    pub fn requests(&mut self) -> &mut AsyncIter<HttpServerRequest> {
+        &mut self.requests
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.server.close();
+    }
+}
+
+/// A request received by [`HttpServer`], which must be answered by
+/// calling [`HttpServerRequest::respond`].
+pub struct HttpServerRequest {
+    req: IncomingMessage,
+    resp: ServerResponse,
+}
+
+impl HttpServerRequest {
+    /// Returns the request target, i.e. the path with the query string.
+    pub fn url(&self) -> String {
+        self.req.url()
+    }
+
+    /// Returns the request method.
+    pub fn method(&self) -> String {
+        self.req.method()
+    }
+
+    /// Sends the response and finishes the request.
+    pub fn respond(self, status_code: u16, content_type: &str, body: &str) {
+        self.resp.set_status_code(status_code);
+        self.resp.set_header("content-type", content_type);
+        self.resp.end(body);
+    }
+}
@@ -8,6 +8,8 @@ pub mod bindings;
 pub mod futures;
 #[cfg(feature = "http_client")]
 pub mod http_client;
+#[cfg(feature = "http_server")]
+pub mod http_server;
 pub mod macros;
 
 pub mod prelude {
@@ -5,6 +5,9 @@ export const CHAT_SERVICE_NAME = "chat";
 
 export interface IChatService extends IService {
     confirmPrompt(prompt: string): Promise<void>;
+    regenerateLastReply(): Promise<void>;
+    editMessage(index: number, text: string): Promise<void>;
+    switchBranch(index: number, alternative: number): Promise<void>;
     syncState(): Promise<void>;
     insertCodeSnippet(contents: string): Promise<void>;
 }
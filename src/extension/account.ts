@@ -0,0 +1,100 @@
+import * as vscode from "vscode";
+import {
+    listAccounts,
+    setWorkspaceAccount,
+    signIn,
+    signOut,
+    switchAccount,
+} from "@crates/cursor-core";
+
+const DEFAULT_ACCOUNT = "default";
+
+export async function handleSignInCommand() {
+    const accounts = await listAccounts();
+    let accountName = DEFAULT_ACCOUNT;
+    if (accounts.length > 0) {
+        // Ask for a name to tell the new account apart from the existing ones.
+        const input = await vscode.window.showInputBox({
+            title: "Sign In / Sign Up",
+            prompt: "Name of the account, signing in with an existing name replaces it.",
+            value: accounts.some((a) => a.name === DEFAULT_ACCOUNT)
+                ? ""
+                : DEFAULT_ACCOUNT,
+            placeHolder: "e.g. personal, work",
+        });
+        if (!input) {
+            return;
+        }
+        accountName = input;
+    }
+    const signInMethod = vscode.workspace
+        .getConfiguration("aicursor")
+        .get<string>("signInMethod", "polling");
+    await signIn(accountName, signInMethod === "loopback");
+}
+
+export async function handleSignOutCommand() {
+    const accounts = await listAccounts();
+    if (accounts.length <= 1) {
+        await signOut(accounts[0]?.name);
+        return;
+    }
+
+    const pick = await vscode.window.showQuickPick(
+        accounts.map((account) => ({
+            label: account.name,
+            description: account.active ? "active" : undefined,
+        })),
+        { title: "Sign Out", placeHolder: "Select the account to sign out" }
+    );
+    if (pick) {
+        await signOut(pick.label);
+    }
+}
+
+export async function handleSwitchAccountCommand() {
+    const accounts = await listAccounts();
+    if (accounts.length === 0) {
+        vscode.commands.executeCommand("aicursor.signInUp");
+        return;
+    }
+
+    const useGlobalItem = "Use the globally active account";
+    const items: vscode.QuickPickItem[] = accounts.map((account) => ({
+        label: account.name,
+        description: [
+            account.active ? "active" : null,
+            account.selectedForWorkspace ? "this workspace" : null,
+            account.status.valid ? null : "session expired",
+        ]
+            .filter((s) => s)
+            .join(", "),
+    }));
+    if (accounts.some((account) => account.selectedForWorkspace)) {
+        items.push({ label: useGlobalItem });
+    }
+    const pick = await vscode.window.showQuickPick(items, {
+        title: "Switch Account",
+        placeHolder: "Select the account to use",
+    });
+    if (!pick) {
+        return;
+    }
+    if (pick.label === useGlobalItem) {
+        await setWorkspaceAccount();
+        return;
+    }
+
+    const everywhereItem = "Everywhere";
+    const workspaceItem = "This workspace only";
+    const scope = vscode.workspace.workspaceFolders
+        ? await vscode.window.showQuickPick([everywhereItem, workspaceItem], {
+              title: `Use "${pick.label}"`,
+          })
+        : everywhereItem;
+    if (scope === everywhereItem) {
+        await switchAccount(pick.label);
+    } else if (scope === workspaceItem) {
+        await setWorkspaceAccount(pick.label);
+    }
+}
@@ -0,0 +1,76 @@
+import * as vscode from "vscode";
+
+const API_KEY_SECRET_KEY = "openai_api_key";
+const API_KEY_SETTING = "openaiApiKey";
+
+let cachedApiKey: string | null = null;
+
+/**
+ * Loads the API key from the secret storage and keeps the cached value
+ * in sync with it.
+ *
+ * Keys configured in the settings by older versions are moved to the
+ * secret storage and removed from the settings.
+ */
+export async function initializeApiKey(
+    context: vscode.ExtensionContext
+): Promise<void> {
+    const { secrets } = context;
+    context.subscriptions.push(
+        secrets.onDidChange(async (e) => {
+            if (e.key === API_KEY_SECRET_KEY) {
+                cachedApiKey = (await secrets.get(API_KEY_SECRET_KEY)) || null;
+            }
+        })
+    );
+
+    const config = vscode.workspace.getConfiguration("aicursor");
+    const legacyApiKey = config.get<string>(API_KEY_SETTING, "");
+    if (legacyApiKey !== "") {
+        await secrets.store(API_KEY_SECRET_KEY, legacyApiKey);
+        const inspection = config.inspect<string>(API_KEY_SETTING);
+        if (inspection?.globalValue !== undefined) {
+            await config.update(
+                API_KEY_SETTING,
+                undefined,
+                vscode.ConfigurationTarget.Global
+            );
+        }
+        if (inspection?.workspaceValue !== undefined) {
+            await config.update(
+                API_KEY_SETTING,
+                undefined,
+                vscode.ConfigurationTarget.Workspace
+            );
+        }
+    }
+
+    cachedApiKey = (await secrets.get(API_KEY_SECRET_KEY)) || null;
+}
+
+export function getApiKey(): string | null {
+    return cachedApiKey;
+}
+
+export async function handleConfigureApiKeyCommand(
+    context: vscode.ExtensionContext
+) {
+    const input = await vscode.window.showInputBox({
+        title: "Configure API Key",
+        prompt: "The key is stored in the secret storage of VS Code. Leave it empty to remove the current key.",
+        placeHolder: "OpenAI API key",
+        password: true,
+        ignoreFocusOut: true,
+    });
+    if (input === undefined) {
+        return;
+    }
+
+    if (input === "") {
+        await context.secrets.delete(API_KEY_SECRET_KEY);
+        cachedApiKey = null;
+    } else {
+        await context.secrets.store(API_KEY_SECRET_KEY, input);
+        cachedApiKey = input;
+    }
+}
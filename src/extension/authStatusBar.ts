@@ -0,0 +1,114 @@
+import * as vscode from "vscode";
+import { AccountInfo, authStatus, getAccountInfo } from "@crates/cursor-core";
+
+const UPDATE_INTERVAL = 60 * 1000;
+
+export class AuthStatusBar implements vscode.Disposable {
+    #item: vscode.StatusBarItem;
+    #timer: NodeJS.Timer;
+    // Warn only once per account and billing period.
+    #warnedQuotas = new Set<string>();
+
+    constructor() {
+        this.#item = vscode.window.createStatusBarItem(
+            vscode.StatusBarAlignment.Right
+        );
+        this.#timer = setInterval(() => this.update(), UPDATE_INTERVAL);
+        this.update();
+        this.#item.show();
+    }
+
+    async update() {
+        const status = await authStatus();
+        const item = this.#item;
+        if (!status.signedIn) {
+            item.text = "$(account) Cursor: Sign In";
+            item.tooltip = "Sign in / sign up to use Cursor AI features";
+            item.command = "aicursor.signInUp";
+            return;
+        }
+
+        const expiresAt =
+            status.expiresAt !== undefined
+                ? new Date(status.expiresAt).toLocaleString()
+                : null;
+        const account = status.account ?? "";
+        item.command = "aicursor.switchAccount";
+        if (!status.valid) {
+            item.text = `$(warning) Cursor: ${account} (Session Expired)`;
+            item.tooltip = expiresAt
+                ? `Session of "${account}" expired at ${expiresAt}, it will be refreshed on the next request`
+                : `Session of "${account}" expired`;
+            return;
+        }
+
+        item.text = `$(account) Cursor: ${account}`;
+        item.tooltip = expiresAt
+            ? `Signed in as "${account}", session valid until ${expiresAt}`
+            : `Signed in as "${account}"`;
+
+        let info: AccountInfo | undefined;
+        try {
+            info = await getAccountInfo();
+        } catch (e) {
+            console.warn("Failed to fetch account info", e);
+        }
+        if (!info) {
+            return;
+        }
+        this.#showAccountInfo(info);
+        this.#warnIfQuotaLow(info);
+    }
+
+    #showAccountInfo(info: AccountInfo) {
+        const item = this.#item;
+        const badges = [info.plan, this.#remainingText(info)].filter(
+            (badge) => !!badge
+        );
+        item.text = `$(${info.quotaLow ? "warning" : "account"}) Cursor: ${
+            info.account
+        }${badges.length > 0 ? ` (${badges.join(", ")})` : ""}`;
+
+        const lines = [item.tooltip as string];
+        if (info.email) {
+            lines.push(`Email: ${info.email}`);
+        }
+        if (info.plan) {
+            lines.push(`Plan: ${info.plan}`);
+        }
+        if (info.fastRequestsUsed !== undefined) {
+            lines.push(
+                `Fast requests: ${info.fastRequestsUsed}${
+                    info.fastRequestsLimit !== undefined
+                        ? ` / ${info.fastRequestsLimit}`
+                        : ""
+                }`
+            );
+        }
+        item.tooltip = lines.join("\n");
+    }
+
+    #remainingText(info: AccountInfo) {
+        return info.fastRequestsRemaining !== undefined
+            ? `${info.fastRequestsRemaining} left`
+            : undefined;
+    }
+
+    #warnIfQuotaLow(info: AccountInfo) {
+        const key = `${info.account}:${info.periodStart ?? ""}`;
+        if (!info.quotaLow || this.#warnedQuotas.has(key)) {
+            return;
+        }
+        this.#warnedQuotas.add(key);
+        vscode.window.showWarningMessage(
+            info.fastRequestsRemaining === 0
+                ? `"${info.account}" has used up its fast requests for this period.`
+                : `"${info.account}" only has ${info.fastRequestsRemaining} fast requests left for this period.`
+        );
+    }
+
+    dispose() {
+        clearInterval(this.#timer);
+        this.#item.dispose();
+    }
+}
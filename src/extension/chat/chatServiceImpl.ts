@@ -3,7 +3,16 @@ import * as vscode from "vscode";
 import { IChatService, CHAT_SERVICE_NAME } from "../../common/chatService";
 import { MessageItemModel } from "../../common/chatService/model";
 import { SelectionRange } from "../generate/core";
-import { chat, resetChat } from "./core";
+import {
+    ChatTurnInfo,
+    getChatTurns,
+    switchChatBranch,
+} from "@crates/cursor-core";
+import { ResultStream } from "../generate/resultStream";
+import { chat, editMessage, regenerateLastReply, resetChat } from "./core";
+import { ChatContextItem, resolveMentions } from "./contextItems";
+
+export const DEFAULT_CHAT_SESSION_ID = "default";
 
 export interface ChatServiceClient {
     handleReadyStateChange?: (isReady: boolean) => void;
@@ -12,6 +21,13 @@ export interface ChatServiceClient {
     handleClearMessage?: () => void;
 }
 
+type ChatRequest = (
+    document: vscode.TextDocument,
+    selection: vscode.Selection,
+    abortSignal: AbortSignal,
+    resultStream: ResultStream<String>
+) => Promise<void>;
+
 export class ChatServiceImpl implements IChatService {
     #currentMessageId = 0;
     #messages = new Array<MessageItemModel>();
@@ -19,6 +35,14 @@ export class ChatServiceImpl implements IChatService {
     #clients = new Set<ChatServiceClient>();
     #currentAbortController: AbortController | null = null;
     #clearSessionScheduled = false;
+    #sessionId = DEFAULT_CHAT_SESSION_ID;
+    /** Attached to the next message. */
+    #contextItems = new Array<ChatContextItem>();
+
+    constructor() {
+        // Show the messages restored from the previous window.
+        this.switchSession(DEFAULT_CHAT_SESSION_ID);
+    }
 
     get name(): string {
         return CHAT_SERVICE_NAME;
@@ -38,6 +62,73 @@ export class ChatServiceImpl implements IChatService {
         }
     }
 
+    get sessionId(): string {
+        return this.#sessionId;
+    }
+
+    get isBusy(): boolean {
+        return this.#currentAbortController !== null;
+    }
+
+    /**
+     * Shows the messages of another chat session in the panel.
+     */
+    switchSession(sessionId: string) {
+        if (this.isBusy) {
+            throw new Error("Cannot switch chat sessions while replying");
+        }
+
+        const turns = getChatTurns(sessionId);
+        this.#sessionId = sessionId;
+        this.#showTurns(turns);
+    }
+
+    /**
+     * Switches to another alternative of an edited message or regenerated
+     * reply.
+     */
+    async switchBranch(index: number, alternative: number): Promise<void> {
+        if (this.isBusy) {
+            throw new Error("Cannot switch branches while replying");
+        }
+
+        switchChatBranch(this.#sessionId, index, alternative);
+        this.#showTurns(getChatTurns(this.#sessionId));
+    }
+
+    #showTurns(turns: ChatTurnInfo[]) {
+        this.#clearMessages();
+        for (const turn of turns) {
+            this.#addMessage({
+                id: "",
+                contents: turn.prompt,
+                isFinished: true,
+            });
+            if (turn.reply) {
+                const interrupted =
+                    turn.status === "cancelled"
+                        ? "\n(Response cancelled)"
+                        : turn.status === "errored"
+                        ? "\n(Response interrupted)"
+                        : "";
+                this.#addMessage({
+                    id: "",
+                    contents: turn.reply + interrupted,
+                    isReply: true,
+                    isFinished: true,
+                });
+            }
+        }
+    }
+
+    #clearMessages() {
+        this.#messageIndex.clear();
+        this.#messages.splice(0, this.#messages.length);
+        for (const client of this.#clients) {
+            client.handleClearMessage?.call(client);
+        }
+    }
+
     clearSession() {
         const abortController = this.#currentAbortController;
         if (abortController) {
@@ -46,12 +137,8 @@ export class ChatServiceImpl implements IChatService {
             return;
         }
 
-        resetChat();
-        this.#messageIndex.clear();
-        this.#messages.splice(0, this.#messages.length);
-        for (const client of this.#clients) {
-            client.handleClearMessage?.call(client);
-        }
+        resetChat(this.#sessionId);
+        this.#clearMessages();
         this.#clearSessionScheduled = false;
 
         vscode.window.showInformationMessage("Chat session has been reset!");
@@ -90,11 +177,79 @@ export class ChatServiceImpl implements IChatService {
         }
     }
 
+    /**
+     * Attaches a file or a selection to the next message.
+     */
+    attachContextItem(item: ChatContextItem) {
+        this.#contextItems.push(item);
+        vscode.window.showInformationMessage(
+            `${vscode.workspace.asRelativePath(item.filePath)} will be ` +
+                "attached to the next chat message."
+        );
+    }
+
     async confirmPrompt(prompt: string): Promise<void> {
+        const attachedItems = this.#contextItems.splice(0);
+        const contextItems = [
+            ...attachedItems,
+            ...(await resolveMentions(prompt)),
+        ];
+        let started = false;
+        try {
+            started = await this.#reply(prompt, (...args) =>
+                chat(this.#sessionId, prompt, contextItems, ...args)
+            );
+        } finally {
+            if (!started) {
+                // Keep the items for the next message.
+                this.#contextItems.unshift(...attachedItems);
+            }
+        }
+    }
+
+    async regenerateLastReply(): Promise<void> {
+        const turns = getChatTurns(this.#sessionId);
+        const last = turns[turns.length - 1];
+        if (!last) {
+            return;
+        }
+
+        await this.#reply(
+            last.prompt,
+            (...args) => regenerateLastReply(this.#sessionId, ...args),
+            turns.slice(0, -1)
+        );
+    }
+
+    async editMessage(index: number, text: string): Promise<void> {
+        const turns = getChatTurns(this.#sessionId);
+        if (index >= turns.length) {
+            return;
+        }
+
+        await this.#reply(
+            text,
+            (...args) => editMessage(this.#sessionId, index, text, ...args),
+            turns.slice(0, index)
+        );
+    }
+
+    /**
+     * Shows the prompt and streams the reply to it.
+     *
+     * @param previousTurns The turns kept before the prompt, when an earlier
+     * turn is replaced.
+     * @returns Whether the reply is started.
+     */
+    async #reply(
+        prompt: string,
+        request: ChatRequest,
+        previousTurns?: ChatTurnInfo[]
+    ): Promise<boolean> {
         if (this.#currentAbortController) {
             // TODO: optimize the UX.
             console.warn("A chat session is in-flight");
-            return;
+            return false;
         }
 
         const editor = vscode.window.activeTextEditor;
@@ -107,6 +262,9 @@ export class ChatServiceImpl implements IChatService {
 
         const { document, selection } = editor;
 
+        if (previousTurns) {
+            this.#showTurns(previousTurns);
+        }
         this.#addMessage({
             id: "",
             contents: prompt,
@@ -142,13 +300,20 @@ export class ChatServiceImpl implements IChatService {
                 this.#updateReadyState(false);
 
                 try {
-                    await chat(
-                        prompt,
+                    await request(
                         document,
                         selection,
                         abortController.signal,
                         resultStream
                     );
+                    if (abortController.signal.aborted) {
+                        // The partial reply is kept in the conversation.
+                        this.#updateMessage(
+                            replyMsgId,
+                            "\n(Response cancelled)",
+                            true
+                        );
+                    }
                 } catch (e) {
                     console.error(e);
                     // TODO: optimize the display of error message.
@@ -166,6 +331,7 @@ export class ChatServiceImpl implements IChatService {
                 }
             }
         );
+        return true;
     }
 
     async syncState(): Promise<void> {
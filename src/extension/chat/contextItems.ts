@@ -0,0 +1,90 @@
+import * as vscode from "vscode";
+
+import { SelectionRange } from "../generate/core";
+
+export interface ChatContextItem {
+    filePath: string;
+    /** The workspace folder of the file, `null` if it's outside of any. */
+    workspaceDirectory: string | null;
+    range: SelectionRange | null;
+    contents: string;
+}
+
+function workspaceDirectory(document: vscode.TextDocument): string | null {
+    return (
+        vscode.workspace.getWorkspaceFolder(document.uri)?.uri.fsPath ?? null
+    );
+}
+
+/**
+ * Attaches the whole document.
+ */
+export function fileContextItem(
+    document: vscode.TextDocument
+): ChatContextItem {
+    return {
+        filePath: document.uri.fsPath,
+        workspaceDirectory: workspaceDirectory(document),
+        range: null,
+        contents: document.getText(),
+    };
+}
+
+/**
+ * Attaches the selected lines of the editor, or `null` if nothing is
+ * selected.
+ */
+export function selectionContextItem(
+    editor: vscode.TextEditor
+): ChatContextItem | null {
+    const { document, selection } = editor;
+    if (selection.isEmpty) {
+        return null;
+    }
+
+    // Whole lines are attached, like the selection of the current file.
+    const lines = new vscode.Range(
+        selection.start.line,
+        0,
+        selection.end.line,
+        document.lineAt(selection.end.line).text.length
+    );
+    return {
+        filePath: document.uri.fsPath,
+        workspaceDirectory: workspaceDirectory(document),
+        range: new SelectionRange(
+            new vscode.Selection(lines.start, lines.end)
+        ),
+        contents: document.getText(lines),
+    };
+}
+
+/**
+ * Resolves the `@path` mentions in the prompt to the files of the
+ * workspace, the mentions that don't name a file are left alone.
+ */
+export async function resolveMentions(
+    prompt: string
+): Promise<ChatContextItem[]> {
+    const items = new Array<ChatContextItem>();
+    const paths = new Set(
+        Array.from(prompt.matchAll(/(?:^|\s)@([\w./\\-]+)/g), (m) => m[1])
+    );
+    for (const path of paths) {
+        for (const folder of vscode.workspace.workspaceFolders ?? []) {
+            const uri = vscode.Uri.joinPath(folder.uri, path);
+            try {
+                const stat = await vscode.workspace.fs.stat(uri);
+                if (stat.type !== vscode.FileType.File) {
+                    continue;
+                }
+                const document = await vscode.workspace.openTextDocument(uri);
+                items.push(fileContextItem(document));
+                break;
+            } catch {
+                // Not a file of this folder.
+            }
+        }
+    }
+    return items;
+}
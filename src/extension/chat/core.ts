@@ -4,52 +4,92 @@ import { ResultStream } from "../generate/resultStream";
 import { Position, SelectionRange } from "../generate/core";
 import {
     chat as rustChat,
+    editMessage as rustEditMessage,
+    regenerateLastReply as rustRegenerateLastReply,
     resetChat as rustResetChat,
 } from "@crates/cursor-core";
+import { ChatContextItem } from "./contextItems";
 
-let isProcessing = false;
+function chatInput(
+    prompt: string,
+    document: vscode.TextDocument,
+    selection: vscode.Selection,
+    abortSignal: AbortSignal,
+    resultStream: ResultStream<String>,
+    contextItems: ChatContextItem[] = []
+) {
+    return {
+        prompt,
+        documentText: document.getText(),
+        filePath: document.uri.fsPath,
+        workspaceDirectory:
+            vscode.workspace.getWorkspaceFolder(document.uri)?.uri.fsPath ??
+            null,
+        selectionRange: new SelectionRange(selection),
+        resultStream,
+        abortSignal,
+        cursor: new Position(
+            selection.active.line,
+            selection.active.character
+        ),
+        languageId: document.languageId,
+        contextItems,
+    };
+}
 
 export async function chat(
+    sessionId: string,
     prompt: string,
+    contextItems: ChatContextItem[],
     document: vscode.TextDocument,
     selection: vscode.Selection,
     abortSignal: AbortSignal,
     resultStream: ResultStream<String>
 ): Promise<void> {
-    if (isProcessing) {
-        throw new Error("A chat session is in-flight");
-    }
-
-    const filePath = document.uri.fsPath;
-    const workspaceDirectory =
-        vscode.workspace.getWorkspaceFolder(document.uri)?.uri.fsPath ?? null;
-    const documentText = document.getText();
-
-    try {
-        isProcessing = true;
-        await rustChat({
+    // The Rust side rejects the message if the session is already replying.
+    await rustChat(
+        chatInput(
             prompt,
-            documentText,
-            filePath,
-            workspaceDirectory,
-            selectionRange: new SelectionRange(selection),
-            resultStream,
+            document,
+            selection,
             abortSignal,
-            cursor: new Position(
-                selection.active.line,
-                selection.active.character
-            ),
-            languageId: document.languageId,
-        });
-    } finally {
-        isProcessing = false;
-    }
+            resultStream,
+            contextItems
+        ),
+        sessionId
+    );
+}
+
+export async function regenerateLastReply(
+    sessionId: string,
+    document: vscode.TextDocument,
+    selection: vscode.Selection,
+    abortSignal: AbortSignal,
+    resultStream: ResultStream<String>
+): Promise<void> {
+    await rustRegenerateLastReply(
+        chatInput("", document, selection, abortSignal, resultStream),
+        sessionId
+    );
 }
 
-export function resetChat() {
-    if (isProcessing) {
-        throw new Error("Cannot reset the chat session while it's in-flight");
-    }
+export async function editMessage(
+    sessionId: string,
+    index: number,
+    text: string,
+    document: vscode.TextDocument,
+    selection: vscode.Selection,
+    abortSignal: AbortSignal,
+    resultStream: ResultStream<String>
+): Promise<void> {
+    await rustEditMessage(
+        chatInput(text, document, selection, abortSignal, resultStream),
+        sessionId,
+        index,
+        text
+    );
+}
 
-    rustResetChat();
+export function resetChat(sessionId: string) {
+    rustResetChat(sessionId);
 }
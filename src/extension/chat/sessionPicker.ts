@@ -0,0 +1,157 @@
+import * as vscode from "vscode";
+import {
+    createChatSession,
+    deleteChatSession,
+    getChatTurns,
+    listChatSessions,
+} from "@crates/cursor-core";
+
+import {
+    DEFAULT_CHAT_SESSION_ID,
+    sharedChatServiceImpl,
+} from "./chatServiceImpl";
+
+async function pickChatSession(title: string): Promise<string | undefined> {
+    const current = sharedChatServiceImpl().sessionId;
+    const sessions = listChatSessions();
+    const pick = await vscode.window.showQuickPick(
+        sessions.map((session) => ({
+            label: session.name,
+            description:
+                session.id === current
+                    ? "(current)"
+                    : session.busy
+                    ? "(replying)"
+                    : "",
+            detail: `${session.turnCount} messages, updated ${new Date(
+                session.updatedAt
+            ).toLocaleString()}`,
+            id: session.id,
+        })),
+        { title }
+    );
+    return pick?.id;
+}
+
+export async function handleNewChatSessionCommand() {
+    const name = await vscode.window.showInputBox({
+        title: "New Chat Session",
+        placeHolder: "Name of the chat session (optional)",
+    });
+    if (name === undefined) {
+        return;
+    }
+
+    try {
+        const session = createChatSession(name || undefined);
+        sharedChatServiceImpl().switchSession(session.id);
+    } catch (e) {
+        vscode.window.showErrorMessage(`${e}`);
+    }
+}
+
+export async function handleSwitchChatSessionCommand() {
+    const sessionId = await pickChatSession("Switch Chat Session");
+    if (!sessionId) {
+        return;
+    }
+
+    try {
+        sharedChatServiceImpl().switchSession(sessionId);
+    } catch (e) {
+        vscode.window.showErrorMessage(`${e}`);
+    }
+}
+
+export async function handleDeleteChatSessionCommand() {
+    const sessionId = await pickChatSession("Delete Chat Session");
+    if (!sessionId) {
+        return;
+    }
+
+    const chatService = sharedChatServiceImpl();
+    try {
+        deleteChatSession(sessionId);
+    } catch (e) {
+        vscode.window.showErrorMessage(`${e}`);
+        return;
+    }
+    if (chatService.sessionId === sessionId) {
+        // The default session always exists, even before its first use.
+        chatService.switchSession(DEFAULT_CHAT_SESSION_ID);
+    }
+}
+
+export async function handleEditChatMessageCommand() {
+    const chatService = sharedChatServiceImpl();
+    const turns = getChatTurns(chatService.sessionId);
+    const pick = await vscode.window.showQuickPick(
+        turns.map((turn, index) => ({
+            label: turn.prompt,
+            description: turn.status === "complete" ? "" : `(${turn.status})`,
+            index,
+        })),
+        {
+            title: "Edit Chat Message",
+            placeHolder: "The later messages of the conversation are dropped",
+        }
+    );
+    if (!pick) {
+        return;
+    }
+
+    const text = await vscode.window.showInputBox({
+        title: "Edit Chat Message",
+        value: turns[pick.index].prompt,
+    });
+    if (!text) {
+        return;
+    }
+    await chatService.editMessage(pick.index, text);
+}
+
+export async function handleSwitchChatBranchCommand() {
+    const chatService = sharedChatServiceImpl();
+    const turns = getChatTurns(chatService.sessionId);
+    const branched = turns
+        .map((turn, index) => ({ turn, index }))
+        .filter(({ turn }) => turn.alternativeCount > 1);
+    if (branched.length === 0) {
+        vscode.window.showInformationMessage(
+            "No message of this chat has been edited or regenerated."
+        );
+        return;
+    }
+
+    const pick = await vscode.window.showQuickPick(
+        branched.map(({ turn, index }) => ({
+            label: turn.prompt,
+            description: `${turn.alternative + 1} of ${turn.alternativeCount}`,
+            index,
+            count: turn.alternativeCount,
+            current: turn.alternative,
+        })),
+        { title: "Switch Chat Branch" }
+    );
+    if (!pick) {
+        return;
+    }
+
+    const alternative = await vscode.window.showQuickPick(
+        Array.from({ length: pick.count }, (_, alternative) => ({
+            label: `Alternative ${alternative + 1}`,
+            description: alternative === pick.current ? "(current)" : "",
+            alternative,
+        })),
+        { title: "Switch Chat Branch" }
+    );
+    if (!alternative) {
+        return;
+    }
+
+    try {
+        await chatService.switchBranch(pick.index, alternative.alternative);
+    } catch (e) {
+        vscode.window.showErrorMessage(`${e}`);
+    }
+}
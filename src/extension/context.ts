@@ -4,9 +4,11 @@ import {
     IGlobalStorage,
     IModelConfiguration,
     IProgress,
+    ISecretStorage,
     RustProgressOptions,
 } from "@crates/cursor-core";
 import { getGlobalState } from "./globalState";
+import { getApiKey } from "./apiKey";
 
 const storageAdapter = {
     get(key: string) {
@@ -17,11 +19,40 @@ const storageAdapter = {
     },
 } satisfies IGlobalStorage;
 
+const workspaceStorageAdapter = {
+    get(key: string) {
+        return getGlobalState().workspaceStorage?.get(key) ?? null;
+    },
+    update(key, value) {
+        getGlobalState().workspaceStorage?.update(key, value);
+    },
+} satisfies IGlobalStorage;
+
+const secretStorageAdapter = {
+    async get(key: string) {
+        return getGlobalState().secrets?.get(key);
+    },
+    async store(key: string, value: string) {
+        await getGlobalState().secrets?.store(key, value);
+    },
+    async delete(key: string) {
+        await getGlobalState().secrets?.delete(key);
+    },
+} satisfies ISecretStorage;
+
 export class ExtensionContext implements IExtensionContext {
     get storage(): IGlobalStorage {
         return storageAdapter;
     }
 
+    get secrets(): ISecretStorage {
+        return secretStorageAdapter;
+    }
+
+    get workspaceStorage(): IGlobalStorage {
+        return workspaceStorageAdapter;
+    }
+
     executeCommand(command: string, ...args: any[]): Thenable<any> {
         return vscode.commands.executeCommand(command, ...args);
     }
@@ -61,15 +92,25 @@ export class ExtensionContext implements IExtensionContext {
 
     getModelConfiguration(): IModelConfiguration {
         const config = vscode.workspace.getConfiguration("aicursor");
-        let apiKey: string | null = config.get("openaiApiKey", null);
-        if (apiKey === "") {
-            apiKey = null;
-        }
         const model = config.get("model", "");
+        const provider = config.get("provider", "cursor");
+        const baseUrl = config.get<string>("baseUrl", "");
 
         return {
-            apiKey: apiKey,
+            apiKey: getApiKey(),
             gptModel: model,
+            provider,
+            baseUrl: baseUrl || null,
+            localModel: config.get("localModel", "codellama"),
+            chatModel: config.get<string>("chatModel", "") || null,
+            editModel: config.get<string>("editModel", "") || null,
+            generateModel: config.get<string>("generateModel", "") || null,
+            projectModel: config.get<string>("projectModel", "") || null,
+            temperature: config.get<number | null>("temperature", null),
+            maxTokens: config.get<number | null>("maxTokens", null),
+            stop: config.get<string[]>("stop", []),
+            rulesFile: config.get<string>("rulesFile", "") || null,
+            authHost: config.get<string>("authHost", "") || null,
         };
     }
 }
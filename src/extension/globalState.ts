@@ -1,14 +1,18 @@
-import { Memento } from "vscode";
+import { Memento, SecretStorage } from "vscode";
 import { GenerateSession } from "./generate";
 
 interface GlobalState {
     activeSession: GenerateSession | null;
     storage: Memento | null;
+    secrets: SecretStorage | null;
+    workspaceStorage: Memento | null;
 }
 
 const globalState: GlobalState = {
     activeSession: null,
     storage: null,
+    secrets: null,
+    workspaceStorage: null,
 };
 
 export function getGlobalState() {
@@ -6,13 +6,31 @@ import { getGlobalState } from "./globalState";
 import { ChatPanelProvider } from "./chat/chatPanelProvider";
 import { sharedChatServiceImpl } from "./chat/chatServiceImpl";
 import {
+    authStatus,
     refreshToken,
     setExtensionContext,
-    signIn,
-    signOut,
 } from "@crates/cursor-core";
 import { ExtensionContext } from "./context";
+import { AuthStatusBar } from "./authStatusBar";
+import { handleConfigureApiKeyCommand, initializeApiKey } from "./apiKey";
+import {
+    handleSignInCommand,
+    handleSignOutCommand,
+    handleSwitchAccountCommand,
+} from "./account";
 import { handleGenerateProjectCommand } from "./project";
+import { handleSelectModelCommand } from "./modelPicker";
+import {
+    fileContextItem,
+    selectionContextItem,
+} from "./chat/contextItems";
+import {
+    handleDeleteChatSessionCommand,
+    handleEditChatMessageCommand,
+    handleSwitchChatBranchCommand,
+    handleNewChatSessionCommand,
+    handleSwitchChatSessionCommand,
+} from "./chat/sessionPicker";
 
 function setHasActiveGenerateSessionContext(value: boolean) {
     vscode.commands.executeCommand(
@@ -54,7 +72,7 @@ async function handleGenerateCodeCommand() {
     setHasActiveGenerateSessionContext(true);
 }
 
-export function activate(context: vscode.ExtensionContext) {
+export async function activate(context: vscode.ExtensionContext) {
     // To use crypto features in WebAssembly, we need to add this polyfill.
     global.crypto = {
         getRandomValues: (arr: Uint8Array) => {
@@ -64,8 +82,14 @@ export function activate(context: vscode.ExtensionContext) {
 
     setExtensionContext(new ExtensionContext());
     getGlobalState().storage = context.globalState;
+    getGlobalState().secrets = context.secrets;
+    getGlobalState().workspaceStorage = context.workspaceState;
+    await initializeApiKey(context);
+
+    const authStatusBar = new AuthStatusBar();
 
     context.subscriptions.push(
+        authStatusBar,
         vscode.commands.registerCommand("aicursor.generateCode", () => {
             handleGenerateCodeCommand();
         }),
@@ -83,21 +107,68 @@ export function activate(context: vscode.ExtensionContext) {
         vscode.commands.registerCommand("aicursor.resetChat", () => {
             sharedChatServiceImpl().clearSession();
         }),
-        vscode.commands.registerCommand("aicursor.signInUp", () => {
-            signIn();
+        vscode.commands.registerCommand(
+            "aicursor.attachFileToChat",
+            async (uri?: vscode.Uri) => {
+                const document = uri
+                    ? await vscode.workspace.openTextDocument(uri)
+                    : vscode.window.activeTextEditor?.document;
+                if (document) {
+                    sharedChatServiceImpl().attachContextItem(
+                        fileContextItem(document)
+                    );
+                }
+            }
+        ),
+        vscode.commands.registerCommand(
+            "aicursor.attachSelectionToChat",
+            () => {
+                const editor = vscode.window.activeTextEditor;
+                const item = editor && selectionContextItem(editor);
+                if (item) {
+                    sharedChatServiceImpl().attachContextItem(item);
+                }
+            }
+        ),
+        vscode.commands.registerCommand("aicursor.regenerateReply", () => {
+            sharedChatServiceImpl().regenerateLastReply();
+        }),
+        vscode.commands.registerCommand("aicursor.editChatMessage", () => {
+            handleEditChatMessageCommand();
+        }),
+        vscode.commands.registerCommand("aicursor.switchChatBranch", () => {
+            handleSwitchChatBranchCommand();
+        }),
+        vscode.commands.registerCommand("aicursor.newChatSession", () => {
+            handleNewChatSessionCommand();
         }),
-        vscode.commands.registerCommand("aicursor.signOut", () => {
-            signOut();
+        vscode.commands.registerCommand("aicursor.switchChatSession", () => {
+            handleSwitchChatSessionCommand();
+        }),
+        vscode.commands.registerCommand("aicursor.deleteChatSession", () => {
+            handleDeleteChatSessionCommand();
+        }),
+        vscode.commands.registerCommand("aicursor.signInUp", async () => {
+            await handleSignInCommand();
+            authStatusBar.update();
+        }),
+        vscode.commands.registerCommand("aicursor.signOut", async () => {
+            await handleSignOutCommand();
+            authStatusBar.update();
+        }),
+        vscode.commands.registerCommand("aicursor.switchAccount", async () => {
+            await handleSwitchAccountCommand();
+            authStatusBar.update();
         }),
         vscode.commands.registerCommand("aicursor.configureApiKey", () => {
-            vscode.commands.executeCommand(
-                "workbench.action.openSettings",
-                "aicursor.openaiApiKey"
-            );
+            handleConfigureApiKeyCommand(context);
         }),
         vscode.commands.registerCommand("aicursor.generateProject", () => {
             handleGenerateProjectCommand();
         }),
+        vscode.commands.registerCommand("aicursor.selectModel", () => {
+            handleSelectModelCommand();
+        }),
         getScratchpadManager().registerTextDocumentContentProvider(),
         vscode.window.registerWebviewViewProvider(
             ChatPanelProvider.viewType,
@@ -105,9 +176,12 @@ export function activate(context: vscode.ExtensionContext) {
         )
     );
 
-    // TODO: No need to refresh the token every time.
-    // If the token is still valid, there is no need to refresh it.
-    refreshToken();
+    // Tokens about to expire are refreshed before each request, only refresh
+    // here when the stored token has already expired.
+    const status = await authStatus();
+    if (status.signedIn && !status.valid) {
+        refreshToken().finally(() => authStatusBar.update());
+    }
 }
 
 export function deactivate() {
@@ -115,4 +189,6 @@ export function deactivate() {
     globalState.activeSession?.dispose();
     globalState.activeSession = null;
     globalState.storage = null;
+    globalState.secrets = null;
+    globalState.workspaceStorage = null;
 }
@@ -0,0 +1,53 @@
+import * as vscode from "vscode";
+import { listModels } from "@crates/cursor-core";
+
+const REFRESH_ITEM = "$(refresh) Refresh Model List";
+
+export async function handleSelectModelCommand(forceRefresh = false) {
+    const config = vscode.workspace.getConfiguration("aicursor");
+    // The local provider has its own model setting.
+    const settingKey =
+        config.get("provider", "cursor") === "local" ? "localModel" : "model";
+    const current = config.get<string>(settingKey, "");
+
+    let models;
+    try {
+        models = await vscode.window.withProgress(
+            {
+                location: vscode.ProgressLocation.Window,
+                title: "Fetching models...",
+            },
+            () => listModels(forceRefresh)
+        );
+    } catch (e) {
+        vscode.window.showErrorMessage(`Failed to fetch models: ${e}`);
+        return;
+    }
+
+    const pick = await vscode.window.showQuickPick(
+        [
+            ...models.map((model) => ({
+                label: model.name,
+                description: `${model.contextWindow.toLocaleString()} tokens context`,
+                picked: model.name === current,
+            })),
+            { label: REFRESH_ITEM, description: "" },
+        ],
+        {
+            title: "Select Model",
+            placeHolder: current ? `Current: ${current}` : undefined,
+        }
+    );
+    if (!pick) {
+        return;
+    }
+    if (pick.label === REFRESH_ITEM) {
+        await handleSelectModelCommand(true);
+        return;
+    }
+    await config.update(
+        settingKey,
+        pick.label,
+        vscode.ConfigurationTarget.Global
+    );
+}